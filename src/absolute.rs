@@ -0,0 +1,78 @@
+use crate::diagnose;
+use crate::speed::SpeedCurve;
+use crate::scroll::{PointerSender, ScrollCommand};
+use evdev::{AbsoluteAxisType, Device, InputEventKind, Key, Synchronization};
+use std::error::Error;
+use std::io;
+
+/// Absolute travel is converted to mouse-like counts so the same deadzone and
+/// curve apply; 10 counts per millimetre puts the default deadzone at 5mm.
+pub const COUNTS_PER_MM: f32 = 10.0;
+/// Used when the kernel doesn't report a resolution for a touchscreen or
+/// tablet. Applied to both axes, which keeps them roughly proportional.
+const ASSUMED_HEIGHT_MM: f32 = 150.0;
+
+/// Physical resolution of an absolute axis, estimated from its range when the
+/// kernel reports none.
+pub fn units_per_mm(device: &Device, axis: AbsoluteAxisType, assumed_mm: f32) -> io::Result<f32> {
+    let info = device.get_abs_state()?[axis.0 as usize];
+    if info.resolution > 0 {
+        Ok(info.resolution as f32)
+    } else {
+        Ok(((info.maximum - info.minimum) as f32 / assumed_mm).max(1.0))
+    }
+}
+
+/// Scrolls while the trigger is held, driven by the distance from where it
+/// was pressed. The trigger is usually BTN_TOUCH, but pens can use a barrel
+/// button so scrolling follows the pen while it hovers.
+pub async fn run(
+    device: Device,
+    curve: SpeedCurve,
+    trigger: Key,
+    mut tx: PointerSender,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let x_units_per_mm = units_per_mm(&device, AbsoluteAxisType::ABS_X, ASSUMED_HEIGHT_MM)?;
+    let y_units_per_mm = units_per_mm(&device, AbsoluteAxisType::ABS_Y, ASSUMED_HEIGHT_MM)?;
+    let abs_state = device.get_abs_state()?;
+    let mut x = abs_state[AbsoluteAxisType::ABS_X.0 as usize].value;
+    let mut y = abs_state[AbsoluteAxisType::ABS_Y.0 as usize].value;
+    let mut held = false;
+    let mut origin: Option<(i32, i32)> = None;
+
+    let name = device.name().unwrap_or("Unknown").to_string();
+    let mut events = device.into_event_stream()?;
+    loop {
+        let ev = events.next_event().await?;
+        trace!("{}: {:?} {}", name, ev.kind(), ev.value());
+        diagnose::input_event(ev.timestamp());
+        match ev.kind() {
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => x = ev.value(),
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => y = ev.value(),
+            InputEventKind::Key(key) if key == trigger => held = ev.value() == 1,
+            // The touch-down position may arrive after BTN_TOUCH in the same
+            // frame, so only act once the whole frame has been read.
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                match origin {
+                    None if held => {
+                        trace!("Start scroll at {}, {}", x, y);
+                        origin = Some((x, y));
+                        tx.send(ScrollCommand::Start { device: name.clone() })?;
+                    }
+                    Some(_) if !held => {
+                        trace!("Stop scroll");
+                        origin = None;
+                        tx.send(ScrollCommand::Stop)?;
+                    }
+                    Some((origin_x, origin_y)) => {
+                        let dx = (x - origin_x) as f32 / x_units_per_mm * COUNTS_PER_MM;
+                        let dy = (y - origin_y) as f32 / y_units_per_mm * COUNTS_PER_MM;
+                        tx.send(ScrollCommand::Update(curve.scroll(dx, dy)))?;
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use futures_util::stream::StreamExt;
+use std::collections::HashMap;
+use std::error::Error;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use zbus::fdo::{DBusProxy, NameOwnerChangedStream};
+use zbus::message::Header;
+use zbus::{connection, interface, Connection};
+
+const BUS_NAME: &str = "io.github.autoscroll";
+const OBJECT_PATH: &str = "/io/github/autoscroll";
+
+/// `io.github.autoscroll.Inhibit` on the session bus.
+pub struct BusInhibitor {
+    conn: Connection,
+    owners: NameOwnerChangedStream,
+    changes: UnboundedReceiver<()>,
+}
+
+impl BusInhibitor {
+    pub async fn serve() -> Result<BusInhibitor, Box<dyn Error>> {
+        let (tx, changes) = unbounded_channel();
+        let cookies = Cookies { held: HashMap::new(), last: 0, tx };
+        let conn = connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, cookies)?
+            .build()
+            .await?;
+        let owners = DBusProxy::new(&conn).await?.receive_name_owner_changed().await?;
+        info!("Taking inhibitors as {} on the session bus", BUS_NAME);
+        Ok(BusInhibitor { conn, owners, changes })
+    }
+
+    pub async fn changed(&mut self) -> Result<(), Box<dyn Error>> {
+        tokio::select! {
+            change = self.owners.next() => {
+                let change = change.ok_or("the bus connection closed")?;
+                let args = change.args()?;
+                if args.new_owner().is_none() {
+                    let gone = args.name().to_string();
+                    self.cookies().await?.get_mut().await.release_owner(&gone);
+                }
+            }
+            change = self.changes.recv() => {
+                change.ok_or("the inhibitor went away")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn held(&self) -> bool {
+        match self.cookies().await {
+            Ok(cookies) => !cookies.get().await.held.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    async fn cookies(&self) -> zbus::Result<zbus::object_server::InterfaceRef<Cookies>> {
+        self.conn.object_server().interface::<_, Cookies>(OBJECT_PATH).await
+    }
+}
+
+/// The cookies handed out, with who holds each.
+struct Cookies {
+    held: HashMap<u32, Holder>,
+    last: u32,
+    tx: UnboundedSender<()>,
+}
+
+struct Holder {
+    /// The caller's unique bus name, to notice it leaving.
+    owner: String,
+    application: String,
+}
+
+impl Cookies {
+    fn release_owner(&mut self, owner: &str) {
+        let before = self.held.len();
+        self.held.retain(|_, holder| holder.owner != owner);
+        if self.held.len() < before {
+            info!("Inhibitor {} left the bus", owner);
+            let _ = self.tx.send(());
+        }
+    }
+}
+
+#[interface(name = "io.github.autoscroll.Inhibit")]
+impl Cookies {
+    /// Holds autoscroll off until `UnInhibit` is called with the returned
+    /// cookie, or the caller disconnects.
+    fn inhibit(&mut self, #[zbus(header)] header: Header<'_>, application: String, reason: String) -> u32 {
+        self.last += 1;
+        let owner = header.sender().map(|sender| sender.to_string()).unwrap_or_default();
+        info!("Inhibited by {} ({}): {}", application, owner, reason);
+        self.held.insert(self.last, Holder { owner, application });
+        let _ = self.tx.send(());
+        self.last
+    }
+
+    fn un_inhibit(&mut self, cookie: u32) {
+        if let Some(holder) = self.held.remove(&cookie) {
+            info!("{} released its inhibitor", holder.application);
+            let _ = self.tx.send(());
+        }
+    }
+}
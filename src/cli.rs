@@ -0,0 +1,250 @@
+use crate::config::{Config, RealtimePolicy};
+use crate::log::LogLevel;
+use crate::preset::Preset;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+const USAGE: &str = "\
+Usage: autoscroll [OPTIONS]
+       autoscroll [OPTIONS] config export [FILE]
+       autoscroll config import FILE
+       autoscroll scroll vertical|horizontal NOTCHES [SEAT]
+       autoscroll pause|resume
+       autoscroll set-preset NAME
+       autoscroll set-device DEVICE|any
+       autoscroll watch
+       autoscroll [OPTIONS] curve [--plot] [DEVICE]
+       autoscroll features
+       autoscroll [OPTIONS] self-test
+
+Options:
+      --preset NAME        slow, default, fast or windows-like
+      --device DEVICE      Only use this mouse (event node, udev ID_PATH or phys)
+      --deadzone COUNTS    Movement before scrolling starts
+      --base-speed SPEED   Wheel clicks per tick for each count past the deadzone
+      --max-speed CLICKS   Wheel clicks per tick at most
+      --pan                Drag the content while the trigger is held
+      --wheel-factor N     Multiply the mouse's own wheel events by N
+      --log-level LEVEL    off, error, info, debug or trace
+  -q, --quiet              Print nothing at all (same as --log-level off)
+  -v, --verbose            Print debug messages; -vv also prints every input event
+      --wait               Wait for a mouse to appear instead of exiting
+      --wait-timeout SECS  Give up waiting after SECS seconds (implies --wait)
+      --list-devices       Show mouse-like devices, their scores and which are used
+      --diagnose SECS      Run for SECS seconds, then report timing and load
+      --nice N             Run at this niceness (negative needs privileges)
+      --realtime POLICY    fifo or rr real-time scheduling (needs privileges)
+      --cpu N              Pin to this CPU core
+      --restore-state      Start paused, preset and device as last set through
+                           pause, set-preset and set-device
+  -h, --help               Print this help
+
+Commands:
+  config export [FILE]     Write the settings in effect, options included, as
+                           one TOML file (to stdout without FILE)
+  config import FILE       Check FILE and make it your config file
+  curve [--plot] [DEVICE]  Show the speed at each distance from the anchor,
+                           using DEVICE's [[device]] entry if given; --plot
+                           draws it as a chart
+  features                 Show which integrations this build has and which
+                           of them can be reached right now
+  self-test                Open the mouse, create the output device and
+                           scroll a click up and down; on X11, also check
+                           a test window receives them
+  scroll AXIS NOTCHES      Scroll through the running daemon; positive
+                           NOTCHES scroll up or right, fractions are fine
+  pause, resume            Ignore the trigger until resumed
+  set-preset NAME          Switch the running daemon to a preset
+  set-device DEVICE|any    Switch the running daemon to one mouse, or back
+                           to every mouse
+  watch                    Follow the running daemon: what it reads, what
+                           pauses it, and why trigger presses don't scroll
+
+Settings come from the config file, then AUTOSCROLL_PRESET, AUTOSCROLL_DEVICE,
+AUTOSCROLL_DEADZONE, AUTOSCROLL_BASE_SPEED, AUTOSCROLL_MAX_SPEED,
+AUTOSCROLL_WHEEL_FACTOR and AUTOSCROLL_LOG_LEVEL, then these flags, each
+overriding the one before. A preset only sets the values that aren't given
+alongside it.
+";
+
+/// Something to do instead of running the daemon.
+#[derive(Debug)]
+pub enum Command {
+    ConfigExport(Option<PathBuf>),
+    ConfigImport(PathBuf),
+    ListDevices,
+    /// Show the speed curve for a device name (or the top-level settings),
+    /// optionally as a chart.
+    Curve { device: Option<String>, plot: bool },
+    /// Report the optional integrations built in and available.
+    Features,
+    /// Check a mouse can be read and scrolling can be sent, end to end.
+    SelfTest,
+    /// A command line for the running daemon's control socket.
+    Control(String),
+    /// Print the running daemon's status lines until interrupted.
+    Watch,
+}
+
+/// Command line flags. Anything given here overrides the config file and
+/// the environment.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Option<Command>,
+    pub preset: Option<Preset>,
+    pub device: Option<String>,
+    pub deadzone: Option<f32>,
+    pub base_speed: Option<f32>,
+    pub max_speed: Option<i32>,
+    pub pan: bool,
+    pub wheel_factor: Option<f32>,
+    pub log_level: Option<LogLevel>,
+    pub wait: bool,
+    pub wait_timeout: Option<u64>,
+    pub diagnose: Option<u64>,
+    pub nice: Option<i32>,
+    pub realtime: Option<RealtimePolicy>,
+    pub cpu: Option<usize>,
+    pub restore_state: bool,
+}
+
+impl Args {
+    pub fn parse() -> Args {
+        match Args::parse_from(std::env::args().skip(1)) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("{}\n\n{}", e, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    fn parse_from(mut iter: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut args = Args::default();
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--preset" => args.preset = Some(value(&arg, &mut iter)?),
+                "--device" => args.device = Some(value(&arg, &mut iter)?),
+                "--deadzone" => args.deadzone = Some(value(&arg, &mut iter)?),
+                "--base-speed" => args.base_speed = Some(value(&arg, &mut iter)?),
+                "--max-speed" => args.max_speed = Some(value(&arg, &mut iter)?),
+                "--pan" => args.pan = true,
+                "--wheel-factor" => args.wheel_factor = Some(value(&arg, &mut iter)?),
+                "--log-level" => args.log_level = Some(value(&arg, &mut iter)?),
+                "-q" | "--quiet" => args.log_level = Some(LogLevel::Off),
+                "-v" | "--verbose" => {
+                    args.log_level = Some(match args.log_level {
+                        Some(LogLevel::Debug | LogLevel::Trace) => LogLevel::Trace,
+                        _ => LogLevel::Debug,
+                    })
+                }
+                "-vv" => args.log_level = Some(LogLevel::Trace),
+                "--wait" => args.wait = true,
+                "--wait-timeout" => {
+                    args.wait = true;
+                    args.wait_timeout = Some(value(&arg, &mut iter)?);
+                }
+                "--list-devices" => args.command = Some(Command::ListDevices),
+                "--diagnose" => args.diagnose = Some(value(&arg, &mut iter)?),
+                "--nice" => args.nice = Some(value(&arg, &mut iter)?),
+                "--realtime" => args.realtime = Some(value(&arg, &mut iter)?),
+                "--cpu" => args.cpu = Some(value(&arg, &mut iter)?),
+                "--restore-state" => args.restore_state = true,
+                "config" => {
+                    args.command = Some(match iter.next().as_deref() {
+                        Some("export") => Command::ConfigExport(iter.next().map(PathBuf::from)),
+                        Some("import") => Command::ConfigImport(
+                            iter.next().ok_or("config import needs a file")?.into(),
+                        ),
+                        _ => return Err("config needs export or import".to_string()),
+                    });
+                }
+                "curve" => {
+                    let mut device = None;
+                    let mut plot = false;
+                    for arg in iter.by_ref() {
+                        match arg.as_str() {
+                            "--plot" => plot = true,
+                            _ if device.is_none() => device = Some(arg),
+                            _ => return Err(format!("unexpected argument: {}", arg)),
+                        }
+                    }
+                    args.command = Some(Command::Curve { device, plot });
+                }
+                "scroll" => {
+                    let rest: Vec<String> = iter.by_ref().collect();
+                    if !(2..=3).contains(&rest.len()) {
+                        return Err("scroll needs an axis and an amount".to_string());
+                    }
+                    args.command = Some(Command::Control(format!("scroll {}", rest.join(" "))));
+                }
+                "features" => args.command = Some(Command::Features),
+                "watch" => args.command = Some(Command::Watch),
+                "self-test" => args.command = Some(Command::SelfTest),
+                "pause" | "resume" => args.command = Some(Command::Control(arg)),
+                "set-preset" | "set-device" => {
+                    let value = iter.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                    args.command = Some(Command::Control(format!("{} {}", arg, value)));
+                }
+                "-h" | "--help" => {
+                    print!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(args)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(preset) = self.preset {
+            preset.apply(config);
+        }
+        if let Some(device) = &self.device {
+            config.pin_device = Some(device.clone());
+        }
+        if let Some(deadzone) = self.deadzone {
+            config.deadzone = deadzone;
+        }
+        if let Some(base_speed) = self.base_speed {
+            config.base_speed = base_speed;
+        }
+        if let Some(max_speed) = self.max_speed {
+            config.max_speed = max_speed;
+        }
+        if self.pan {
+            config.pan = true;
+        }
+        if let Some(factor) = self.wheel_factor {
+            config.wheel.factor = factor;
+        }
+        if let Some(log_level) = self.log_level {
+            config.log_level = log_level;
+        }
+        if self.wait {
+            config.wait_for_device = true;
+        }
+        if self.wait_timeout.is_some() {
+            config.wait_timeout = self.wait_timeout;
+        }
+        if self.nice.is_some() {
+            config.priority.nice = self.nice;
+        }
+        if self.realtime.is_some() {
+            config.priority.realtime = self.realtime;
+        }
+        if self.cpu.is_some() {
+            config.priority.cpu = self.cpu;
+        }
+    }
+}
+
+fn value<T: FromStr>(flag: &str, iter: &mut impl Iterator<Item = String>) -> Result<T, String> {
+    let value = iter.next().ok_or_else(|| format!("{} needs a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid {}: {}", flag, value))
+}
@@ -0,0 +1,770 @@
+use crate::handedness;
+use crate::log::LogLevel;
+use crate::preset::Preset;
+use crate::speed::ScrollSpeed;
+use crate::window::WindowClass;
+use evdev::Key;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const DEFAULT_DEADZONE: f32 = 50.0;
+pub const DEFAULT_BASE_SPEED: f32 = 0.05;
+pub const DEFAULT_MAX_SPEED: i32 = 5;
+/// Roughly one pixel of content per count with the usual 15 pixels per notch.
+pub const DEFAULT_PAN_SPEED: f32 = 8.0;
+/// About a screenful of wheel scrolling.
+const DEFAULT_CLICKS_PER_PAGE: i32 = 10;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Only read from the config file; fills in whichever of deadzone,
+    /// base_speed, max_speed and curve the file leaves out.
+    pub preset: Option<Preset>,
+    pub deadzone: f32,
+    /// Horizontal size of a `square` or `ellipse` deadzone; `deadzone` otherwise.
+    pub deadzone_horizontal: Option<f32>,
+    pub deadzone_shape: DeadzoneShape,
+    /// What `deadzone` and `base_speed` measure distance in.
+    pub distance_unit: DistanceUnit,
+    /// Adapt each mouse's deadzone to how much the hand shakes while the
+    /// trigger is held, starting from `deadzone`.
+    pub auto_deadzone: bool,
+    /// Where distance is measured from once the trigger is down.
+    pub origin: Origin,
+    pub base_speed: f32,
+    pub max_speed: i32,
+    pub curve: Curve,
+    /// Also scroll sideways when the pointer moves left or right.
+    pub horizontal: bool,
+    pub invert_vertical: bool,
+    pub invert_horizontal: bool,
+    /// Speed builds up over this long after leaving the deadzone, up to what
+    /// the distance asks for; 0 for the full speed at once.
+    pub ramp_ms: u64,
+    /// Different speeds for scrolling up, down, left or right.
+    pub direction: DirectionSpeeds,
+    /// After the trigger is released, ignore it bouncing and the pointer
+    /// settling for this long.
+    pub cooldown_ms: u64,
+    /// Ignore the trigger while another mouse button is held, e.g. mid-drag.
+    pub inhibit_during_drag: bool,
+    /// Pressing another mouse button mid-scroll ends the scroll, and the
+    /// button still clicks.
+    pub cancel_on_click: bool,
+    /// Keep the pointer on a line through the anchor while scrolling, by
+    /// holding back its motion across the scroll.
+    pub pointer_rail: bool,
+    /// When to stop scrolling because our logind session is in the background.
+    pub session_pause: SessionPause,
+    /// Drag the content with the trigger held instead of autoscrolling.
+    pub pan: bool,
+    /// Hi-res wheel units (120 per notch) per count of pointer motion when panning.
+    pub pan_speed: f32,
+    /// Swap BTN_LEFT and BTN_RIGHT in triggers, as the desktop does for
+    /// the pointer. Detected from the desktop when unset.
+    pub left_handed: Option<bool>,
+    /// Only use this mouse: an event node (or a /dev/input/by-* link to
+    /// one), a udev ID_PATH, or a phys string.
+    pub pin_device: Option<String>,
+    /// Keep looking for a mouse at startup instead of exiting, for mice
+    /// (e.g. Bluetooth) that connect after the daemon starts.
+    pub wait_for_device: bool,
+    /// Seconds to wait before giving up; waits forever when unset.
+    pub wait_timeout: Option<u64>,
+    pub log_level: LogLevel,
+    /// Show an arrow at the anchor while scrolling (X11 only).
+    pub osd: bool,
+    /// Light the keyboard's Scroll Lock LED while scrolling.
+    pub scroll_lock_led: bool,
+    /// Where wheel events go; `ydotool` reuses a running ydotoold and
+    /// `portal` goes through xdg-desktop-portal instead of creating our own
+    /// uinput device.
+    pub output: OutputBackend,
+    /// ydotoold's socket; defaults to $YDOTOOL_SOCKET or /tmp/.ydotool_socket.
+    pub ydotool_socket: Option<PathBuf>,
+    /// Unix socket for `autoscroll scroll` and other scripts; defaults to
+    /// `$XDG_RUNTIME_DIR/autoscroll.sock`.
+    pub control_socket: Option<PathBuf>,
+    /// Autoscroll is off while this file exists; defaults to
+    /// `$XDG_RUNTIME_DIR/autoscroll.inhibit`.
+    pub inhibit_file: Option<PathBuf>,
+    /// Shell commands run when scrolling starts and stops on a seat.
+    pub on_scroll_start: Option<String>,
+    pub on_scroll_stop: Option<String>,
+    /// Run when scrolling starts from a different device than last time.
+    pub on_device_change: Option<String>,
+    pub touchpad: TouchpadConfig,
+    pub switch: SwitchConfig,
+    pub power_save: PowerSaveConfig,
+    pub limiter: LimiterConfig,
+    pub priority: PriorityConfig,
+    pub wheel: WheelConfig,
+    pub passthrough: PassthroughConfig,
+    #[serde(rename = "device")]
+    pub devices: Vec<DeviceConfig>,
+    #[serde(rename = "app")]
+    pub apps: Vec<AppProfile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            preset: None,
+            deadzone: DEFAULT_DEADZONE,
+            deadzone_horizontal: None,
+            deadzone_shape: DeadzoneShape::default(),
+            distance_unit: DistanceUnit::default(),
+            auto_deadzone: false,
+            origin: Origin::default(),
+            base_speed: DEFAULT_BASE_SPEED,
+            max_speed: DEFAULT_MAX_SPEED,
+            curve: Curve::default(),
+            horizontal: false,
+            invert_vertical: false,
+            invert_horizontal: false,
+            ramp_ms: 0,
+            direction: DirectionSpeeds::default(),
+            cooldown_ms: 30,
+            inhibit_during_drag: false,
+            cancel_on_click: false,
+            pointer_rail: false,
+            session_pause: SessionPause::default(),
+            pan: false,
+            pan_speed: DEFAULT_PAN_SPEED,
+            left_handed: None,
+            pin_device: None,
+            wait_for_device: false,
+            wait_timeout: None,
+            log_level: LogLevel::default(),
+            osd: false,
+            scroll_lock_led: false,
+            output: OutputBackend::default(),
+            ydotool_socket: None,
+            control_socket: None,
+            inhibit_file: None,
+            on_scroll_start: None,
+            on_scroll_stop: None,
+            on_device_change: None,
+            touchpad: TouchpadConfig::default(),
+            switch: SwitchConfig::default(),
+            power_save: PowerSaveConfig::default(),
+            limiter: LimiterConfig::default(),
+            priority: PriorityConfig::default(),
+            wheel: WheelConfig::default(),
+            passthrough: PassthroughConfig::default(),
+            devices: Vec::new(),
+            apps: Vec::new(),
+        }
+    }
+}
+
+/// Commands to run around a scroll, taken from the top-level `on_scroll_*` keys.
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub on_scroll_start: Option<String>,
+    pub on_scroll_stop: Option<String>,
+    pub on_device_change: Option<String>,
+}
+
+/// Per-device settings, selected by a case-insensitive substring of the device
+/// name. Speed settings left out fall back to the top-level ones.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    pub name: String,
+    pub mode: DeviceMode,
+    pub speed_source: SpeedSource,
+    /// Button that engages scrolling, e.g. "BTN_STYLUS". Defaults to the
+    /// middle button, or touching the surface for absolute devices.
+    #[serde(deserialize_with = "deserialize_key", serialize_with = "serialize_key")]
+    pub trigger: Option<Key>,
+    pub deadzone: Option<f32>,
+    pub auto_deadzone: Option<bool>,
+    pub base_speed: Option<f32>,
+    pub max_speed: Option<i32>,
+    pub curve: Option<Curve>,
+}
+
+/// Per-application overrides, selected by the focused window's WM_CLASS
+/// (either part, case-insensitive) when scrolling starts.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppProfile {
+    pub class: String,
+    /// Caps wheel clicks per tick, for apps whose scroll handling can't keep up.
+    pub max_speed: Option<i32>,
+    /// Minimum time between wheel events. Ticks in between are added up and
+    /// sent as one bigger step, so the speed stays the same.
+    pub min_interval_ms: Option<u64>,
+    /// What autoscrolling turns into in this app.
+    pub scroll_as: ScrollAs,
+    /// Wheel clicks that add up to one page with `scroll_as = "space"`.
+    pub clicks_per_page: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollAs {
+    #[default]
+    Wheel,
+    /// Space pages down and Shift+Space up, for PDF readers and slide
+    /// shows that page better than they scroll. Sideways scrolling is dropped.
+    Space,
+}
+
+impl AppProfile {
+    pub fn matches(&self, window: &WindowClass) -> bool {
+        self.class.eq_ignore_ascii_case(&window.class)
+            || self.class.eq_ignore_ascii_case(&window.instance)
+    }
+
+    /// Caps the speed at `max_speed`; zero or less stops scrolling in the app.
+    pub fn limit(&self, speed: ScrollSpeed) -> ScrollSpeed {
+        match self.max_speed.map(|max| max.max(0)) {
+            Some(max) => ScrollSpeed {
+                vertical: speed.vertical.clamp(-max, max),
+                horizontal: speed.horizontal.clamp(-max, max),
+            },
+            None => speed,
+        }
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        Duration::from_millis(self.min_interval_ms.unwrap_or(0))
+    }
+
+    pub fn clicks_per_page(&self) -> i32 {
+        self.clicks_per_page.unwrap_or(DEFAULT_CLICKS_PER_PAGE).max(1)
+    }
+}
+
+/// Touchpads start scrolling on a touch-and-hold with several fingers, since
+/// they have no middle button to drag with.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TouchpadConfig {
+    pub enabled: bool,
+    pub fingers: usize,
+    pub hold_ms: u64,
+}
+
+impl Default for TouchpadConfig {
+    fn default() -> Self {
+        TouchpadConfig {
+            enabled: false,
+            fingers: 3,
+            hold_ms: 300,
+        }
+    }
+}
+
+/// Per-direction speeds, by the way the content scrolls.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectionSpeeds {
+    pub up: DirectionSpeed,
+    pub down: DirectionSpeed,
+    pub left: DirectionSpeed,
+    pub right: DirectionSpeed,
+}
+
+/// Overrides the top-level (or device) speeds when scrolling one way.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectionSpeed {
+    pub base_speed: Option<f32>,
+    pub max_speed: Option<i32>,
+}
+
+/// Single-switch scrolling: the trigger toggles a fixed-speed scroll
+/// instead of having to be held while moving the mouse.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwitchConfig {
+    pub enabled: bool,
+    /// Wheel clicks per tick.
+    pub speed: i32,
+    /// Presses at least this long stop scrolling; shorter ones start or reverse it.
+    pub long_press_ms: u64,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        SwitchConfig {
+            enabled: false,
+            speed: 1,
+            long_press_ms: 600,
+        }
+    }
+}
+
+/// Guards against a runaway scroll, e.g. from a release event that got lost.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimiterConfig {
+    /// Wheel clicks sent in any one second at most; 0 for no limit.
+    pub max_clicks_per_second: i32,
+    /// Stop a scroll that has gone on this long, as if the trigger had been
+    /// released; 0 for no limit.
+    pub max_scroll_secs: u64,
+}
+
+impl Default for LimiterConfig {
+    fn default() -> Self {
+        LimiterConfig {
+            max_clicks_per_second: 200,
+            max_scroll_secs: 300,
+        }
+    }
+}
+
+/// What changes while running on battery, to wake the CPU less often.
+/// There's nothing to do for idle devices: their readers sleep until the
+/// kernel has an event, so a mouse that isn't used never wakes the daemon.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerSaveConfig {
+    pub when: PowerSaveWhen,
+    /// Keep showing the OSD arrow.
+    pub osd: bool,
+    /// Keep lighting the Scroll Lock LED.
+    pub scroll_lock_led: bool,
+    /// Send scroll ticks at most this often, several clicks at a time; 0
+    /// leaves them as they are.
+    pub min_interval_ms: u64,
+    /// How often to look for a mouse while waiting for one at startup, at
+    /// least once a second.
+    pub wait_poll_secs: u64,
+}
+
+impl PowerSaveConfig {
+    pub fn min_interval(&self) -> Duration {
+        Duration::from_millis(self.min_interval_ms)
+    }
+
+    pub fn wait_poll(&self) -> Duration {
+        Duration::from_secs(self.wait_poll_secs.max(1))
+    }
+}
+
+impl Default for PowerSaveConfig {
+    fn default() -> Self {
+        PowerSaveConfig {
+            when: PowerSaveWhen::default(),
+            osd: false,
+            scroll_lock_led: false,
+            min_interval_ms: 0,
+            wait_poll_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerSaveWhen {
+    #[default]
+    Battery,
+    Always,
+    Never,
+}
+
+/// Changes to the mouse's own wheel. Enabling any of it grabs the mouse and
+/// re-emits its events through a virtual copy.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WheelConfig {
+    /// Multiplies every wheel event, e.g. 0.5 for half or 3.0 for triple
+    /// distance per notch.
+    pub factor: f32,
+    /// Extra speed for each notch per second the wheel is spun at; 0 turns
+    /// acceleration off.
+    pub acceleration: f32,
+    /// Acceleration never multiplies wheel motion by more than this, on top
+    /// of `factor`.
+    pub max_factor: f32,
+    /// Tilting the wheel while autoscrolling makes it a click per tick
+    /// slower (left) or faster (right) until the trigger is released.
+    pub tilt_nudge: bool,
+}
+
+impl WheelConfig {
+    pub fn enabled(&self) -> bool {
+        self.factor != 1.0 || self.acceleration > 0.0 || self.tilt_nudge
+    }
+}
+
+impl Default for WheelConfig {
+    fn default() -> Self {
+        WheelConfig {
+            factor: 1.0,
+            acceleration: 0.0,
+            max_factor: 8.0,
+            tilt_nudge: false,
+        }
+    }
+}
+
+/// How the two virtual devices of a grabbed mouse present themselves.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassthroughConfig {
+    /// The copy carrying motion and buttons.
+    pub pointer: VirtualIdentity,
+    /// The copy carrying only the wheel.
+    pub wheel: VirtualIdentity,
+}
+
+/// Name and USB ids for a virtual device; each defaults to the real mouse's
+/// (the name with a prefix).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VirtualIdentity {
+    /// `{name}` is replaced with the real mouse's name.
+    pub name: Option<String>,
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+}
+
+/// Scheduling for the daemon, so scroll ticks stay on time under heavy load.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriorityConfig {
+    pub nice: Option<i32>,
+    /// Real-time policy; needs CAP_SYS_NICE or a suitable RLIMIT_RTPRIO.
+    pub realtime: Option<RealtimePolicy>,
+    pub realtime_priority: i32,
+    /// Keep the daemon on this CPU core.
+    pub cpu: Option<usize>,
+}
+
+impl Default for PriorityConfig {
+    fn default() -> Self {
+        PriorityConfig {
+            nice: None,
+            realtime: None,
+            realtime_priority: 10,
+            cpu: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RealtimePolicy {
+    Fifo,
+    Rr,
+}
+
+impl FromStr for RealtimePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fifo" => Ok(RealtimePolicy::Fifo),
+            "rr" => Ok(RealtimePolicy::Rr),
+            _ => Err(format!("unknown real-time policy: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputBackend {
+    #[default]
+    Uinput,
+    Ydotool,
+    Portal,
+}
+
+/// Where scrolling starts when the pointer moves along both axes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeadzoneShape {
+    /// Each axis starts scrolling on its own once it passes its threshold.
+    #[default]
+    Square,
+    /// Both axes start together once the pointer is `deadzone` away in any direction.
+    Circle,
+    /// Like `Circle`, with `deadzone_horizontal` as the sideways radius.
+    Ellipse,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DistanceUnit {
+    /// Raw device counts.
+    #[default]
+    Counts,
+    /// Percent of the screen height, taking one count as one pixel, so a
+    /// config feels the same on a laptop and on a 4K monitor.
+    Screen,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Origin {
+    /// Where the pointer was when the trigger went down.
+    #[default]
+    Press,
+    /// Where the pointer was when it first moved after the press, as on
+    /// Windows, so a twitch while pressing doesn't count towards the distance.
+    FirstMotion,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SessionPause {
+    /// Keep scrolling whatever logind says.
+    Never,
+    /// While another VT or the login screen is in front.
+    Inactive,
+    /// Also while the session is locked.
+    #[default]
+    InactiveOrLocked,
+}
+
+/// How speed grows with distance past the deadzone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    Quadratic,
+    Logarithmic,
+    /// `max_speed` as soon as the pointer leaves the deadzone, however far it goes.
+    Constant,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceMode {
+    #[default]
+    Mouse,
+    /// Logarithmic distance scaling, so small thumb motions don't max out the speed.
+    Trackball,
+    /// Touchscreens and tablets: scrolls while touched, by distance from the touch-down point.
+    Absolute,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeedSource {
+    /// Speed follows the distance from the point where the trigger was pressed.
+    #[default]
+    Displacement,
+    /// Speed follows how fast the pointer is moving and decays when it stops.
+    Velocity,
+}
+
+impl Config {
+    /// Loads the first config file found, falling back to defaults when there is none.
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        for path in config_paths() {
+            if path.exists() {
+                info!("Loading config: {}", path.display());
+                let text = fs::read_to_string(&path)?;
+                let config = parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+                return Ok(config);
+            }
+        }
+        Ok(Config::default())
+    }
+
+    /// The settings in effect as one self-contained TOML file. Presets are
+    /// already folded into the values, so the file doesn't depend on them.
+    pub fn export(&self) -> Result<String, Box<dyn Error>> {
+        let mut config = self.clone();
+        config.preset = None;
+        let mut value = toml::Value::try_from(&config)?;
+        tidy_floats(&mut value);
+        Ok(toml::to_string_pretty(&value)?)
+    }
+
+    /// Checks a shared config file and installs it as the user's config,
+    /// keeping the one it replaces as `config.toml.bak`.
+    pub fn import(source: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let text = fs::read_to_string(source)?;
+        parse(&text).map_err(|e| format!("{}: {}", source.display(), e))?;
+
+        let target = user_config_path().ok_or("neither XDG_CONFIG_HOME nor HOME is set")?;
+        if target.exists() {
+            fs::copy(&target, target.with_extension("toml.bak"))?;
+        } else if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&target, text)?;
+        Ok(target)
+    }
+
+    /// Applies `AUTOSCROLL_*` environment variables on top of the config file.
+    pub fn apply_env(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(preset) = env_value::<Preset>("AUTOSCROLL_PRESET")? {
+            preset.apply(self);
+        }
+        if let Ok(device) = std::env::var("AUTOSCROLL_DEVICE") {
+            self.pin_device = Some(device);
+        }
+        if let Some(deadzone) = env_value("AUTOSCROLL_DEADZONE")? {
+            self.deadzone = deadzone;
+        }
+        if let Some(base_speed) = env_value("AUTOSCROLL_BASE_SPEED")? {
+            self.base_speed = base_speed;
+        }
+        if let Some(max_speed) = env_value("AUTOSCROLL_MAX_SPEED")? {
+            self.max_speed = max_speed;
+        }
+        if let Some(factor) = env_value("AUTOSCROLL_WHEEL_FACTOR")? {
+            self.wheel.factor = factor;
+        }
+        if let Some(log_level) = env_value("AUTOSCROLL_LOG_LEVEL")? {
+            self.log_level = log_level;
+        }
+        Ok(())
+    }
+
+    /// Turns `screen` distances into device counts for a screen this many
+    /// pixels high. Done once at startup; exports keep the percentages.
+    pub fn apply_screen_height(&mut self, height: u32) {
+        if self.distance_unit != DistanceUnit::Screen {
+            return;
+        }
+        let counts_per_percent = height as f32 / 100.0;
+        self.deadzone *= counts_per_percent;
+        self.deadzone_horizontal = self.deadzone_horizontal.map(|d| d * counts_per_percent);
+        self.base_speed /= counts_per_percent;
+        for direction in [
+            &mut self.direction.up,
+            &mut self.direction.down,
+            &mut self.direction.left,
+            &mut self.direction.right,
+        ] {
+            direction.base_speed = direction.base_speed.map(|s| s / counts_per_percent);
+        }
+        for device in &mut self.devices {
+            device.deadzone = device.deadzone.map(|d| d * counts_per_percent);
+            device.base_speed = device.base_speed.map(|s| s / counts_per_percent);
+        }
+        self.distance_unit = DistanceUnit::Counts;
+    }
+
+    pub fn hooks(&self) -> Hooks {
+        Hooks {
+            on_scroll_start: self.on_scroll_start.clone(),
+            on_scroll_stop: self.on_scroll_stop.clone(),
+            on_device_change: self.on_device_change.clone(),
+        }
+    }
+
+    /// Settings for a device, with its trigger already turned into the
+    /// button the kernel reports.
+    pub fn device(&self, device_name: &str) -> DeviceConfig {
+        let device_name = device_name.to_lowercase();
+        let mut device = self
+            .devices
+            .iter()
+            .find(|d| !d.name.is_empty() && device_name.contains(&d.name.to_lowercase()))
+            .cloned()
+            .unwrap_or_default();
+        let left_handed = self.left_handed.unwrap_or(false);
+        device.trigger = device.trigger.map(|key| handedness::physical(key, left_handed));
+        device
+    }
+}
+
+fn parse(text: &str) -> Result<Config, toml::de::Error> {
+    let mut table: toml::Table = toml::from_str(text)?;
+    if let Some(preset) = table.get("preset").cloned() {
+        let preset = Preset::deserialize(preset)?;
+        for (key, value) in preset.values() {
+            table.entry(key).or_insert(value);
+        }
+    }
+    table.try_into()
+}
+
+fn env_value<T: FromStr>(name: &str) -> Result<Option<T>, String> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid {}: {}", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Key>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse::<Key>()
+        .map(Some)
+        .map_err(|_| D::Error::custom(format!("unknown key or button: {}", name)))
+}
+
+fn serialize_key<S: Serializer>(key: &Option<Key>, serializer: S) -> Result<S::Ok, S::Error> {
+    match key {
+        Some(key) => serializer.serialize_str(&format!("{:?}", key)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// f32 settings turn into f64 on the way to TOML, so 0.05 would come out as
+/// 0.05000000074505806. Rounds them back to what was written.
+fn tidy_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(f) => *f = (*f as f32).to_string().parse().unwrap_or(*f),
+        toml::Value::Array(array) => array.iter_mut().for_each(tidy_floats),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| tidy_floats(v)),
+        _ => {}
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        Some(PathBuf::from(dir).join("autoscroll/config.toml"))
+    } else {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/autoscroll/config.toml"))
+    }
+}
+
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = user_config_path().into_iter().collect();
+    paths.push(PathBuf::from("/etc/autoscroll/config.toml"));
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(max_speed: Option<i32>) -> AppProfile {
+        AppProfile {
+            class: "test".to_string(),
+            max_speed,
+            min_interval_ms: None,
+            scroll_as: ScrollAs::Wheel,
+            clicks_per_page: None,
+        }
+    }
+
+    #[test]
+    fn app_limit() {
+        let speed = ScrollSpeed { vertical: -4, horizontal: 2 };
+        assert_eq!(app(None).limit(speed), speed);
+        assert_eq!(app(Some(1)).limit(speed), ScrollSpeed { vertical: -1, horizontal: 1 });
+        assert!(app(Some(0)).limit(speed).is_zero());
+        assert!(app(Some(-3)).limit(speed).is_zero());
+    }
+
+    #[test]
+    fn power_save_waits() {
+        let mut power = PowerSaveConfig::default();
+        assert_eq!(power.min_interval(), Duration::ZERO);
+        power.wait_poll_secs = 0;
+        assert_eq!(power.wait_poll(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,278 @@
+use crate::instance;
+use crate::preset::Preset;
+use crate::scroll::ScrollCommand;
+use crate::status;
+use crate::wheel::HI_RES_PER_NOTCH;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+/// The most notches one `scroll` command can ask for either way.
+const MAX_NOTCHES: f32 = 1000.0;
+
+/// Where the daemon says whether a request it can't check up front worked;
+/// dropping it counts as success.
+pub type Reply = oneshot::Sender<Result<(), String>>;
+
+/// Something asked for on the control socket.
+#[derive(Debug)]
+pub enum Request {
+    /// Scroll on the named seat right away.
+    Scroll { seat: String, command: ScrollCommand, reply: Reply },
+    /// Stop (or go back to) reacting to the trigger.
+    Pause(bool),
+    /// Switch every device to a preset's speed settings.
+    Preset(Preset, Reply),
+    /// Only use this mouse from now on, or any with `None`.
+    Device(Option<String>, Reply),
+    /// Someone started watching, and wants to hear how things stand.
+    Watch,
+}
+
+/// Line-based commands on a Unix socket, so window manager bindings and
+/// voice control can scroll through the daemon's device instead of each
+/// needing uinput access of their own. Every line gets one line back:
+/// `ok` or `error: <reason>`.
+///
+/// - `scroll <vertical|horizontal> <notches> [seat]` scrolls right away.
+///   Notches may be fractional; positive scrolls up or right, like the wheel.
+///   A seat with no mouse being read is an error.
+/// - `pause` and `resume` turn the trigger off and on.
+/// - `set-preset <name>` switches to a preset.
+/// - `set-device <device>|any` pins a mouse, like `pin_device`. Both answer
+///   with an error, and change nothing, when no mouse would be left to use.
+/// - `watch` answers `ok`, then keeps the connection for status lines:
+///   which devices are read and what pauses scrolling, then why each
+///   trigger press that didn't scroll didn't.
+pub struct Control {
+    listener: UnixListener,
+    path: PathBuf,
+    tx: UnboundedSender<Request>,
+    rx: UnboundedReceiver<Request>,
+}
+
+impl Control {
+    /// Listens on `path`, replacing a socket left behind by a daemon that
+    /// didn't shut down cleanly.
+    pub fn bind(path: &Path) -> io::Result<Control> {
+        if net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use, is autoscroll already running?", path.display()),
+            ));
+        }
+        let _ = fs::remove_file(path);
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+        let (tx, rx) = unbounded_channel();
+        Ok(Control {
+            listener,
+            path: path.to_path_buf(),
+            tx,
+            rx,
+        })
+    }
+
+    /// Serves connections until one of them asks for something.
+    pub async fn next(&mut self) -> Request {
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve(stream, self.tx.clone()));
+                    }
+                    Err(e) => debug!("Control connection failed: {}", e),
+                },
+                Some(request) = self.rx.recv() => return request,
+            }
+        }
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+async fn serve(stream: UnixStream, tx: UnboundedSender<Request>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(read).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim() == "watch" {
+            stream_status(lines, write, tx).await;
+            return;
+        }
+        let (reply, answer) = oneshot::channel();
+        let reply = match parse(&line, reply) {
+            Ok(request) => {
+                if tx.send(request).is_err() {
+                    break;
+                }
+                match answer.await {
+                    Ok(Err(e)) => format!("error: {}", e),
+                    _ => "ok".to_string(),
+                }
+            }
+            Err(e) => format!("error: {}", e),
+        };
+        if write.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Streams status lines until the watcher hangs up.
+async fn stream_status(
+    mut lines: tokio::io::Lines<tokio::io::BufReader<OwnedReadHalf>>,
+    mut write: OwnedWriteHalf,
+    tx: UnboundedSender<Request>,
+) {
+    let mut status = status::subscribe();
+    if tx.send(Request::Watch).is_err() || write.write_all(b"ok\n").await.is_err() {
+        return;
+    }
+    loop {
+        let line = tokio::select! {
+            line = status.recv() => match line {
+                Ok(line) => line,
+                Err(RecvError::Lagged(missed)) => format!("({} status lines dropped)", missed),
+                Err(RecvError::Closed) => return,
+            },
+            // Anything the watcher sends is ignored; only hanging up counts
+            read = lines.next_line() => match read {
+                Ok(Some(_)) => continue,
+                _ => return,
+            },
+        };
+        if write.write_all(format!("{}\n", line).as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn parse(line: &str, reply: Reply) -> Result<Request, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["scroll", axis, amount, rest @ ..] if rest.len() <= 1 => {
+            let notches: f32 = amount
+                .parse()
+                .ok()
+                .filter(|n: &f32| n.is_finite())
+                .ok_or_else(|| format!("invalid amount: {}", amount))?;
+            if notches.abs() > MAX_NOTCHES {
+                return Err(format!("amount out of range: {} (at most {} notches)", amount, MAX_NOTCHES));
+            }
+            let units = notches * HI_RES_PER_NOTCH as f32;
+            let command = match *axis {
+                "vertical" => ScrollCommand::Pan { vertical: units, horizontal: 0.0 },
+                "horizontal" => ScrollCommand::Pan { vertical: 0.0, horizontal: units },
+                _ => return Err(format!("unknown axis: {}", axis)),
+            };
+            let seat = rest.first().unwrap_or(&"seat0").to_string();
+            Ok(Request::Scroll { seat, command, reply })
+        }
+        ["scroll", ..] => Err("usage: scroll <vertical|horizontal> <notches> [seat]".to_string()),
+        ["pause"] => Ok(Request::Pause(true)),
+        ["resume"] => Ok(Request::Pause(false)),
+        ["set-preset", name] => Ok(Request::Preset(name.parse()?, reply)),
+        ["set-device", "any"] => Ok(Request::Device(None, reply)),
+        ["set-device", device] => Ok(Request::Device(Some(device.to_string()), reply)),
+        [] => Err("empty command".to_string()),
+        [command, ..] => Err(format!("unknown command: {}", command)),
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/autoscroll.sock`, or `/run/autoscroll.sock` without a
+/// user session.
+pub fn default_socket() -> PathBuf {
+    instance::runtime_dir().join("autoscroll.sock")
+}
+
+/// Sends one command line to a running daemon and returns its reply.
+pub fn request(path: &Path, line: &str) -> io::Result<String> {
+    let mut stream = connect(path)?;
+    writeln!(stream, "{}", line)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
+
+/// Asks a running daemon to `watch` and prints what it reports until it
+/// goes away or the user interrupts.
+pub fn watch(path: &Path) -> io::Result<()> {
+    let stream = connect(path)?;
+    writeln!(&stream, "watch")?;
+
+    let mut lines = BufReader::new(stream).lines();
+    match lines.next().transpose()? {
+        Some(reply) if reply == "ok" => {}
+        Some(reply) => return Err(io::Error::other(reply.trim_start_matches("error: ").to_string())),
+        None => return Ok(()),
+    }
+    for line in lines {
+        println!("{}", line?);
+    }
+    Ok(())
+}
+
+fn connect(path: &Path) -> io::Result<net::UnixStream> {
+    net::UnixStream::connect(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Can't reach the daemon at {}: {}", path.display(), e),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Request, String> {
+        super::parse(line, oneshot::channel().0)
+    }
+
+    fn pan(line: &str) -> Option<(f32, f32)> {
+        match parse(line) {
+            Ok(Request::Scroll { command: ScrollCommand::Pan { vertical, horizontal }, .. }) => {
+                Some((vertical, horizontal))
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn scroll_amounts() {
+        assert_eq!(pan("scroll vertical -3"), Some((-360.0, 0.0)));
+        assert_eq!(pan("scroll horizontal 0.5 seat1"), Some((0.0, 60.0)));
+        assert_eq!(pan("scroll vertical 1000"), Some((120000.0, 0.0)));
+        assert!(parse("scroll vertical 1e9").is_err());
+        assert!(parse("scroll vertical -1001").is_err());
+        assert!(parse("scroll vertical inf").is_err());
+        assert!(parse("scroll vertical NaN").is_err());
+        assert!(parse("scroll diagonal 1").is_err());
+    }
+
+    #[test]
+    fn other_commands() {
+        assert!(matches!(parse("pause"), Ok(Request::Pause(true))));
+        assert!(matches!(parse("resume"), Ok(Request::Pause(false))));
+        assert!(matches!(parse("set-device any"), Ok(Request::Device(None, _))));
+        assert!(matches!(parse("set-device /dev/input/event3"), Ok(Request::Device(Some(_), _))));
+        assert!(parse("set-preset nonsense").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("reboot").is_err());
+    }
+}
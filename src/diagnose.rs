@@ -0,0 +1,179 @@
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Ticks later than this mean something else is hogging the CPU.
+const LATE_TICK: Duration = Duration::from_millis(10);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATS: Mutex<Option<Stats>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Stats {
+    input_events: u64,
+    scroll_wakeups: u64,
+    /// How long after it was due each scroll tick actually ran.
+    tick_lateness: Vec<Duration>,
+    /// Time from the kernel timestamping an input event to us handling it.
+    input_latency: Vec<Duration>,
+    max_backlog: usize,
+    process: ProcessCounters,
+}
+
+/// Counters the kernel keeps for us, read at the start and end of the run.
+#[derive(Debug, Default, Clone, Copy)]
+struct ProcessCounters {
+    read_syscalls: u64,
+    write_syscalls: u64,
+    voluntary_switches: u64,
+    involuntary_switches: u64,
+    cpu_ticks: u64,
+}
+
+impl ProcessCounters {
+    fn read() -> ProcessCounters {
+        let field = |text: &str, name: &str| {
+            text.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0)
+        };
+        let io = fs::read_to_string("/proc/self/io").unwrap_or_default();
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        // utime and stime are fields 14 and 15, after the parenthesised command name
+        let stat = fs::read_to_string("/proc/self/stat").unwrap_or_default();
+        let cpu_ticks = stat
+            .rsplit_once(')')
+            .map(|(_, rest)| {
+                rest.split_whitespace()
+                    .skip(11)
+                    .take(2)
+                    .filter_map(|v| v.parse::<u64>().ok())
+                    .sum()
+            })
+            .unwrap_or(0);
+
+        ProcessCounters {
+            read_syscalls: field(&io, "syscr:"),
+            write_syscalls: field(&io, "syscw:"),
+            voluntary_switches: field(&status, "voluntary_ctxt_switches:"),
+            involuntary_switches: field(&status, "nonvoluntary_ctxt_switches:"),
+            cpu_ticks,
+        }
+    }
+}
+
+/// Starts collecting statistics for `--diagnose`.
+pub fn start() {
+    *STATS.lock().unwrap() = Some(Stats {
+        process: ProcessCounters::read(),
+        ..Stats::default()
+    });
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn record(update: impl FnOnce(&mut Stats)) {
+    if ENABLED.load(Ordering::Relaxed)
+        && let Some(stats) = STATS.lock().unwrap().as_mut()
+    {
+        update(stats);
+    }
+}
+
+pub fn input_event(timestamp: SystemTime) {
+    record(|stats| {
+        stats.input_events += 1;
+        if let Ok(latency) = timestamp.elapsed() {
+            stats.input_latency.push(latency);
+        }
+    });
+}
+
+/// A scroll task woke up, `lateness` after its tick was due if it woke for one.
+pub fn scroll_wakeup(lateness: Option<Duration>, backlog: usize) {
+    record(|stats| {
+        stats.scroll_wakeups += 1;
+        stats.tick_lateness.extend(lateness);
+        stats.max_backlog = stats.max_backlog.max(backlog);
+    });
+}
+
+/// Prints what was collected since `start`, with suggestions.
+pub fn report(started: Instant) {
+    ENABLED.store(false, Ordering::Relaxed);
+    let Some(mut stats) = STATS.lock().unwrap().take() else {
+        return;
+    };
+    let seconds = started.elapsed().as_secs_f64().max(0.001);
+    let end = ProcessCounters::read();
+    let start = stats.process;
+    let clock_ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    let per_second = |count: u64| count as f64 / seconds;
+
+    println!("Diagnostics over {:.1}s:", seconds);
+    println!("  input events:        {:.1}/s", per_second(stats.input_events));
+    println!("  scroll task wakeups: {:.1}/s", per_second(stats.scroll_wakeups));
+    println!(
+        "  context switches:    {:.1}/s voluntary, {:.1}/s involuntary",
+        per_second(end.voluntary_switches - start.voluntary_switches),
+        per_second(end.involuntary_switches - start.involuntary_switches)
+    );
+    println!(
+        "  syscalls:            {:.1}/s read, {:.1}/s write",
+        per_second(end.read_syscalls - start.read_syscalls),
+        per_second(end.write_syscalls - start.write_syscalls)
+    );
+    println!(
+        "  CPU:                 {:.2}%",
+        (end.cpu_ticks - start.cpu_ticks) as f64 / clock_ticks / seconds * 100.0
+    );
+    println!("  input latency:       {}", summary(&mut stats.input_latency));
+    println!("  scroll tick lateness: {}", summary(&mut stats.tick_lateness));
+    println!("  max channel backlog: {}", stats.max_backlog);
+
+    let mut suggestions = Vec::new();
+    if percentile(&stats.tick_lateness, 0.99).is_some_and(|p99| p99 > LATE_TICK)
+        || percentile(&stats.input_latency, 0.99).is_some_and(|p99| p99 > LATE_TICK)
+    {
+        suggestions.push(
+            "Events are handled late under load; try [priority] nice = -5 or realtime = \"fifo\".",
+        );
+    }
+    if stats.max_backlog > 16 {
+        suggestions.push(
+            "Commands pile up faster than they're handled; a lower max_speed or an app \
+             min_interval_ms gives the output less to do.",
+        );
+    }
+    if stats.input_events == 0 {
+        suggestions.push("No input arrived; check that the right mouse was picked at startup.");
+    }
+    if suggestions.is_empty() {
+        println!("Nothing looks wrong.");
+    }
+    for suggestion in suggestions {
+        println!("Suggestion: {}", suggestion);
+    }
+}
+
+fn percentile(sorted: &[Duration], fraction: f64) -> Option<Duration> {
+    let last = sorted.len().checked_sub(1)?;
+    Some(sorted[(last as f64 * fraction).round() as usize])
+}
+
+fn summary(samples: &mut [Duration]) -> String {
+    if samples.is_empty() {
+        return "no samples".to_string();
+    }
+    samples.sort();
+    let average = samples.iter().sum::<Duration>() / samples.len() as u32;
+    format!(
+        "avg {:.2?}, p50 {:.2?}, p99 {:.2?}, max {:.2?} ({} samples)",
+        average,
+        percentile(samples, 0.5).unwrap_or_default(),
+        percentile(samples, 0.99).unwrap_or_default(),
+        samples[samples.len() - 1],
+        samples.len()
+    )
+}
@@ -0,0 +1,19 @@
+use std::error::Error;
+
+/// Stands in for the D-Bus inhibitor in builds without the `dbus` feature;
+/// can't be served, so only the inhibit file works.
+pub enum BusInhibitor {}
+
+impl BusInhibitor {
+    pub async fn serve() -> Result<BusInhibitor, Box<dyn Error>> {
+        Err("this build has no D-Bus support".into())
+    }
+
+    pub async fn changed(&mut self) -> Result<(), Box<dyn Error>> {
+        match *self {}
+    }
+
+    pub async fn held(&self) -> bool {
+        match *self {}
+    }
+}
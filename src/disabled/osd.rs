@@ -0,0 +1,17 @@
+use crate::speed::ScrollSpeed;
+
+/// Stands in for the speed indicator in builds without the `x11` feature,
+/// doing nothing.
+pub struct Osd;
+
+impl Osd {
+    pub fn new(_max_speed: i32) -> Osd {
+        Osd
+    }
+
+    pub fn show(&mut self) {}
+
+    pub fn update(&mut self, _speed: ScrollSpeed) {}
+
+    pub fn hide(&mut self) {}
+}
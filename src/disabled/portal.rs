@@ -0,0 +1,23 @@
+use std::error::Error;
+
+/// Stands in for the desktop portal output in builds without the `wayland`
+/// feature; can't be connected.
+pub enum RemoteDesktop {}
+
+impl RemoteDesktop {
+    pub async fn connect() -> Result<RemoteDesktop, Box<dyn Error>> {
+        Err("this build has no desktop portal support (the wayland feature)".into())
+    }
+
+    pub async fn scroll(&self, _vertical: i32, _horizontal: i32) -> Result<(), Box<dyn Error>> {
+        match *self {}
+    }
+
+    pub async fn scroll_smooth(&self, _vertical: f32, _horizontal: f32) -> Result<(), Box<dyn Error>> {
+        match *self {}
+    }
+
+    pub async fn key(&self, _code: u16, _pressed: bool) -> Result<(), Box<dyn Error>> {
+        match *self {}
+    }
+}
@@ -0,0 +1,20 @@
+use crate::config::SessionPause;
+use std::error::Error;
+
+/// Stands in for following the logind session in builds without the `dbus`
+/// feature; can't be connected.
+pub enum SessionWatch {}
+
+impl SessionWatch {
+    pub async fn connect(_pause: SessionPause) -> Result<SessionWatch, Box<dyn Error>> {
+        Err("this build has no D-Bus support".into())
+    }
+
+    pub fn paused(&self) -> bool {
+        match *self {}
+    }
+
+    pub async fn changed(&mut self) -> Result<bool, Box<dyn Error>> {
+        match *self {}
+    }
+}
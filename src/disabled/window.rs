@@ -0,0 +1,17 @@
+/// The focused window's WM_CLASS, e.g. instance "slack" and class "Slack".
+#[derive(Debug, Clone, Default)]
+pub struct WindowClass {
+    pub instance: String,
+    pub class: String,
+}
+
+/// Stands in for the focused window lookup in builds without the `x11`
+/// feature, so app profiles never match.
+#[derive(Default)]
+pub struct WindowTracker {}
+
+impl WindowTracker {
+    pub fn active_class(&mut self) -> Option<WindowClass> {
+        None
+    }
+}
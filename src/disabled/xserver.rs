@@ -0,0 +1,15 @@
+use std::error::Error;
+
+/// Stands in for asking the X server in builds without the `x11` feature.
+/// Whether there's one to ask is only guessed from `DISPLAY`.
+pub fn reachable() -> bool {
+    std::env::var_os("DISPLAY").is_some()
+}
+
+pub fn left_handed() -> Result<bool, Box<dyn Error>> {
+    Err("this build has no X11 support".into())
+}
+
+pub fn screen_height() -> Result<u32, Box<dyn Error>> {
+    Err("this build has no X11 support".into())
+}
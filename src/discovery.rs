@@ -0,0 +1,376 @@
+use crate::config::{Config, DeviceMode};
+use crate::passthrough::{self, VIRTUAL_DEVICE_PREFIX};
+use crate::touchpad;
+use evdev::{AbsoluteAxisType, BusType, Device, Key, LedType, RelativeAxisType};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::io::unix::AsyncFd;
+
+/// Opens every `/dev/input/event*` node we have permission to read, except
+/// our own virtual devices (by name, or by node for passthrough copies
+/// that may be named anything).
+fn event_devices() -> io::Result<Vec<(PathBuf, Device)>> {
+    let mut devices = Vec::new();
+
+    for entry in fs::read_dir("/dev/input")? {
+        let path = entry?.path();
+
+        if path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .is_some_and(|f| f.starts_with("event"))
+            && !passthrough::is_own(&path)
+            && let Ok(device) = Device::open(&path)
+            && !device.name().is_some_and(|name| name.starts_with(VIRTUAL_DEVICE_PREFIX))
+        {
+            devices.push((path, device));
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Looks up a udev property on an event node, or on the closest parent that
+/// has it (udev usually sets ID_SEAT on a parent, for example).
+fn udev_property(path: &str, key: &str) -> Option<String> {
+    let syspath = Path::new("/sys/class/input").join(Path::new(path).file_name()?);
+    let context = libudev::Context::new().ok()?;
+
+    let mut device = libudev::Device::from_syspath(&context, &syspath).ok();
+    while let Some(current) = device {
+        if let Some(value) = current.property_value(key) {
+            return Some(value.to_string_lossy().into_owned());
+        }
+        device = current.parent();
+    }
+    None
+}
+
+/// The logind seat a device belongs to.
+pub fn seat_of(path: &str) -> String {
+    udev_property(path, "ID_SEAT").unwrap_or_else(|| "seat0".to_string())
+}
+
+/// A device scoring at least this is surely a mouse and is used alongside
+/// any other mice on its seat. Lower scorers are only used when a seat has
+/// nothing better.
+const MOUSE_SCORE: i32 = 5;
+
+struct MouseCandidate {
+    seat: String,
+    score: i32,
+    /// What the score is made of, for --list-devices.
+    reasons: Vec<String>,
+    /// Stable across reboots and replugging into the same port, unlike the
+    /// event node number, so identical mice can be told apart.
+    identity: String,
+    phys: String,
+    path: String,
+    name: String,
+}
+
+impl MouseCandidate {
+    fn matches(&self, pin: &str) -> bool {
+        let resolved = fs::canonicalize(pin).ok();
+        pin == self.path
+            || pin == self.identity
+            || pin == self.phys
+            || resolved.is_some_and(|p| p == Path::new(&self.path))
+    }
+}
+
+/// How much a pointing device looks like a real mouse, judged by what it
+/// can do and what udev thinks of it rather than by its name alone.
+fn score(path: &str, device: &Device) -> (i32, Vec<String>) {
+    let keys = device.supported_keys();
+    let has_key = |key: Key| keys.is_some_and(|keys| keys.contains(key));
+    let is_udev = |property: &str| udev_property(path, property).is_some_and(|v| v == "1");
+    let bus = device.input_id().bus_type();
+    let letters = is_udev("ID_INPUT_KEYBOARD") || has_key(Key::KEY_A);
+    let wheel = device
+        .supported_relative_axes()
+        .is_some_and(|axes| axes.contains(RelativeAxisType::REL_WHEEL));
+    // Combo receivers put a whole keyboard and mouse on one node; a middle
+    // button and a wheel next to the letters mean there's a real mouse there
+    let combo = letters && wheel && has_key(Key::BTN_MIDDLE);
+
+    let checks = [
+        (4, "udev ID_INPUT_MOUSE", is_udev("ID_INPUT_MOUSE")),
+        (2, "middle button", has_key(Key::BTN_MIDDLE)),
+        (2, "wheel", wheel),
+        (
+            1,
+            "USB, Bluetooth or PS/2",
+            [BusType::BUS_USB, BusType::BUS_BLUETOOTH, BusType::BUS_I8042].contains(&bus),
+        ),
+        (-2, "virtual device", bus == BusType::BUS_VIRTUAL),
+        (-3, "has letter keys", letters && !combo),
+        (0, "keyboard and mouse in one", combo),
+        (
+            -1,
+            "\"keyboard\" in name",
+            !combo && device.name().is_some_and(|name| name.to_lowercase().contains("keyboard")),
+        ),
+    ];
+
+    let mut score = 0;
+    let mut reasons = Vec::new();
+    for (points, reason, applies) in checks {
+        if applies {
+            score += points;
+            reasons.push(format!("{:+} {}", points, reason));
+        }
+    }
+    (score, reasons)
+}
+
+/// Everything with buttons and relative X/Y motion, best first on each seat.
+fn mouse_candidates() -> io::Result<Vec<MouseCandidate>> {
+    let mut candidates = Vec::new();
+
+    for (path, device) in event_devices()? {
+        let has_mouse_buttons = device.supported_keys().is_some_and(|keys| {
+            keys.contains(Key::BTN_LEFT)
+                || keys.contains(Key::BTN_MIDDLE)
+                || keys.contains(Key::BTN_RIGHT)
+        });
+
+        let has_relative_movement =
+            device.supported_relative_axes().is_some_and(|axes| {
+                axes.contains(RelativeAxisType::REL_X)
+                    && axes.contains(RelativeAxisType::REL_Y)
+            });
+
+        if has_mouse_buttons && has_relative_movement {
+            let path = path.to_string_lossy().to_string();
+            let phys = device.physical_path().unwrap_or("").to_string();
+            let identity = udev_property(&path, "ID_PATH")
+                .or_else(|| (!phys.is_empty()).then(|| phys.clone()))
+                .unwrap_or_else(|| path.clone());
+            let (score, reasons) = score(&path, &device);
+
+            candidates.push(MouseCandidate {
+                seat: seat_of(&path),
+                score,
+                reasons,
+                identity,
+                phys,
+                path,
+                name: device.name().unwrap_or("Unknown").to_string(),
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| {
+        a.seat
+            .cmp(&b.seat)
+            .then(b.score.cmp(&a.score))
+            .then(a.identity.cmp(&b.identity))
+    });
+    Ok(candidates)
+}
+
+/// Every sure mouse on each seat, or the best of the rest when a seat has none.
+fn select(candidates: &[MouseCandidate]) -> Vec<&MouseCandidate> {
+    let mut selected: Vec<&MouseCandidate> = Vec::new();
+    for candidate in candidates {
+        let best_on_seat = selected.iter().find(|s| s.seat == candidate.seat);
+        if best_on_seat.is_none_or(|best| {
+            candidate.score >= MOUSE_SCORE || best.score == candidate.score
+        }) {
+            selected.push(candidate);
+        }
+    }
+    selected
+}
+
+/// Picks the most likely mice on each seat, or only the pinned one. Every
+/// selected mouse gets its own reader, so each tracks its own buttons.
+fn find_mouse_devices(pin: Option<&str>) -> io::Result<Vec<String>> {
+    let mut mouse_candidates = mouse_candidates()?;
+    for candidate in &mouse_candidates {
+        info!(
+            "Found potential mouse device: {} ({}) [{}], score {}",
+            candidate.path, candidate.name, candidate.identity, candidate.score
+        );
+    }
+
+    if let Some(pin) = pin {
+        mouse_candidates.retain(|c| c.matches(pin));
+        if mouse_candidates.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Pinned mouse device {} not found", pin),
+            ));
+        }
+    }
+
+    let selected = select(&mouse_candidates);
+    for candidate in &selected {
+        info!(
+            "Selected mouse device for {}: {} ({}) [{}]",
+            candidate.seat, candidate.path, candidate.name, candidate.identity
+        );
+    }
+
+    if selected.is_empty() {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No mouse device found",
+        ))
+    } else {
+        Ok(selected.into_iter().map(|c| c.path.clone()).collect())
+    }
+}
+
+/// Prints every mouse-like device with its score, marking the ones that
+/// would be used, for working out why the wrong one gets picked.
+pub fn list_devices(config: &Config) -> io::Result<()> {
+    let mut candidates = mouse_candidates()
+        .map_err(|e| io::Error::new(e.kind(), format!("Can't read /dev/input: {}", e)))?;
+    if let Some(pin) = &config.pin_device {
+        candidates.retain(|c| c.matches(pin));
+        println!("Pinned to {}", pin);
+    }
+    let selected: Vec<String> = select(&candidates).iter().map(|c| c.path.clone()).collect();
+
+    if candidates.is_empty() {
+        println!("No mouse-like devices found (or no permission to read /dev/input)");
+    }
+    for candidate in &candidates {
+        let marker = if selected.contains(&candidate.path) { "*" } else { " " };
+        println!(
+            "{} {} ({}) on {}, score {}",
+            marker, candidate.path, candidate.name, candidate.seat, candidate.score
+        );
+        println!("    id: {}", candidate.identity);
+        println!("    {}", candidate.reasons.join(", "));
+    }
+
+    if config.touchpad.enabled {
+        for path in find_touchpads()? {
+            println!("* {} (touchpad)", path);
+        }
+    }
+    for path in find_absolute_devices(config)? {
+        println!("* {} (absolute)", path);
+    }
+    println!("\n* = used. Scores of {} or more count as a mouse.", MOUSE_SCORE);
+    Ok(())
+}
+
+fn find_touchpads() -> io::Result<Vec<String>> {
+    let mut touchpads = Vec::new();
+
+    for (path, device) in event_devices()? {
+        if touchpad::is_touchpad(&device) {
+            info!(
+                "Found touchpad device: {} ({})",
+                path.display(),
+                device.name().unwrap_or("Unknown")
+            );
+            touchpads.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(touchpads)
+}
+
+/// Touchscreens and tablets are only used when a `[[device]]` entry opts them
+/// in with `mode = "absolute"`, since touching them normally has other meanings.
+fn find_absolute_devices(config: &Config) -> io::Result<Vec<String>> {
+    let mut found = Vec::new();
+
+    for (path, device) in event_devices()? {
+        let has_position = device.supported_absolute_axes().is_some_and(|axes| {
+            axes.contains(AbsoluteAxisType::ABS_X) && axes.contains(AbsoluteAxisType::ABS_Y)
+        });
+        let device_name = device.name().unwrap_or("Unknown");
+
+        if has_position && config.device(device_name).mode == DeviceMode::Absolute {
+            info!("Found absolute device: {} ({})", path.display(), device_name);
+            found.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(found)
+}
+
+/// Keyboards on `seat` that have a Scroll Lock LED, opened for writing.
+pub fn find_scroll_lock_keyboards(seat: &str) -> io::Result<Vec<Device>> {
+    let mut found = Vec::new();
+
+    for (path, device) in event_devices()? {
+        let path = path.to_string_lossy();
+        if device
+            .supported_leds()
+            .is_some_and(|leds| leds.contains(LedType::LED_SCROLLL))
+            && seat_of(&path) == seat
+        {
+            info!(
+                "Using Scroll Lock LED on {} ({})",
+                path,
+                device.name().unwrap_or("Unknown")
+            );
+            found.push(device);
+        }
+    }
+
+    Ok(found)
+}
+
+pub struct InputDevices {
+    pub mice: Vec<String>,
+    pub touchpads: Vec<String>,
+    pub absolute: Vec<String>,
+}
+
+/// Finds everything we can scroll from. Only fails when there is nothing at all.
+pub fn find_input_devices(config: &Config) -> io::Result<InputDevices> {
+    let touchpads = if config.touchpad.enabled {
+        find_touchpads()?
+    } else {
+        Vec::new()
+    };
+    let absolute = find_absolute_devices(config)?;
+    let mice = match find_mouse_devices(config.pin_device.as_deref()) {
+        Ok(paths) => paths,
+        Err(e) if !touchpads.is_empty() || !absolute.is_empty() => {
+            info!("{}, continuing without a mouse", e);
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(InputDevices { mice, touchpads, absolute })
+}
+
+/// Wakes up when udev announces a new input device, for waiting on a mouse
+/// without rescanning /dev/input in a loop.
+pub struct Hotplug {
+    socket: AsyncFd<libudev::MonitorSocket>,
+}
+
+impl Hotplug {
+    pub fn new() -> io::Result<Hotplug> {
+        let context = libudev::Context::new()?;
+        let mut monitor = libudev::Monitor::new(&context)?;
+        monitor.match_subsystem("input")?;
+        Ok(Hotplug {
+            socket: AsyncFd::new(monitor.listen()?)?,
+        })
+    }
+
+    pub async fn added(&mut self) -> io::Result<()> {
+        loop {
+            let mut guard = self.socket.readable_mut().await?;
+            while let Some(event) = guard.get_inner_mut().receive_event() {
+                if matches!(event.event_type(), libudev::EventType::Add) {
+                    return Ok(());
+                }
+            }
+            guard.clear_ready();
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::config::{Config, OutputBackend, PowerSaveWhen, SessionPause};
+use crate::instance;
+use crate::xserver;
+use std::ffi::CString;
+use std::path::Path;
+
+/// Optional integrations, whether this build has them, and what they need
+/// at runtime.
+const FEATURES: [(&str, bool, &str); 3] = [
+    ("x11", cfg!(feature = "x11"), "OSD, app profiles, handedness and screen size"),
+    ("dbus", cfg!(feature = "dbus"), "logind session pausing, UPower and inhibitors"),
+    ("wayland", cfg!(feature = "wayland"), "output through the desktop portal"),
+];
+
+/// Prints which integrations are built in and whether what they talk to
+/// can be reached right now, for `autoscroll features`.
+pub fn print() {
+    println!("Built with:");
+    for (name, enabled, what) in FEATURES {
+        println!("  {:<8} {:<4} {}", name, if enabled { "yes" } else { "no" }, what);
+    }
+
+    println!("\nAvailable now:");
+    let checks = [
+        ("uinput", writable("/dev/uinput")),
+        ("X11 display", xserver::reachable()),
+        ("session bus", session_bus()),
+        ("system bus", Path::new("/run/dbus/system_bus_socket").exists()),
+        ("Wayland", std::env::var_os("WAYLAND_DISPLAY").is_some()),
+        ("runtime dir", instance::runtime_dir().is_dir()),
+    ];
+    for (name, available) in checks {
+        println!("  {:<12} {}", name, if available { "yes" } else { "no" });
+    }
+}
+
+/// Logs settings that ask for an integration this build leaves out, which
+/// would otherwise just quietly do nothing.
+pub fn warn_missing(config: &Config) {
+    let wanted = [
+        ("osd", config.osd, "x11"),
+        ("[[app]] profiles", !config.apps.is_empty(), "x11"),
+        ("session_pause", config.session_pause != SessionPause::Never, "dbus"),
+        ("power_save from UPower", config.power_save.when == PowerSaveWhen::Battery, "dbus"),
+        ("output = \"portal\"", config.output == OutputBackend::Portal, "wayland"),
+    ];
+    for (setting, used, feature) in wanted {
+        let built = FEATURES.iter().any(|(name, enabled, _)| *name == feature && *enabled);
+        if used && !built {
+            info!("{} needs the {} feature, which this build leaves out", setting, feature);
+        }
+    }
+}
+
+fn writable(path: &str) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Whether there's a session bus, from `DBUS_SESSION_BUS_ADDRESS` or the
+/// usual socket in the runtime directory. Addresses other than a socket
+/// path are taken on trust.
+fn session_bus() -> bool {
+    match std::env::var("DBUS_SESSION_BUS_ADDRESS") {
+        Ok(address) => match address.split(',').find_map(|part| part.strip_prefix("unix:path=")) {
+            Some(path) => Path::new(path).exists(),
+            None => true,
+        },
+        Err(_) => instance::runtime_dir().join("bus").exists(),
+    }
+}
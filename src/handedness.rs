@@ -0,0 +1,34 @@
+use crate::xserver;
+use evdev::Key;
+use std::process::Command;
+
+/// Whether the desktop has swapped the primary and secondary buttons. The
+/// X server's pointer mapping (as set by `xmodmap` or most X11 desktops) is
+/// checked first, then GNOME's setting, which also covers Wayland sessions.
+pub fn detect() -> Option<bool> {
+    match xserver::left_handed() {
+        Ok(left_handed) => return Some(left_handed),
+        Err(e) => debug!("Can't read the X11 pointer mapping: {}", e),
+    }
+
+    let output = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.peripherals.mouse", "left-handed"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// The button the kernel reports for one named as the user sees it, so a
+/// left-handed user's "BTN_LEFT" is the button under their index finger.
+pub fn physical(key: Key, left_handed: bool) -> Key {
+    match key {
+        Key::BTN_LEFT if left_handed => Key::BTN_RIGHT,
+        Key::BTN_RIGHT if left_handed => Key::BTN_LEFT,
+        key => key,
+    }
+}
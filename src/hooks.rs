@@ -0,0 +1,28 @@
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Runs a user command through `sh -c` without waiting for it, so a slow
+/// hook can't hold up scrolling. The device involved is passed in
+/// `AUTOSCROLL_DEVICE_NAME`. Failures only get logged.
+pub fn run(name: &'static str, command: &str, device: &str) {
+    debug!("Running {} hook: {}", name, command);
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("AUTOSCROLL_DEVICE_NAME", device)
+        .stdin(Stdio::null())
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if !status.success() => error!("{} hook {}", name, status),
+                    Ok(_) => {}
+                    Err(e) => error!("{} hook failed: {}", name, e),
+                }
+            });
+        }
+        Err(e) => error!("Can't run {} hook: {}", name, e),
+    }
+}
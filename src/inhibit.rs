@@ -0,0 +1,132 @@
+use crate::bus_inhibit::BusInhibitor;
+use crate::instance;
+use std::error::Error;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use tokio::io::unix::AsyncFd;
+
+/// The inhibit file's usual place, next to the control socket.
+pub fn default_file() -> PathBuf {
+    instance::runtime_dir().join("autoscroll.inhibit")
+}
+
+/// Lets other programs hold autoscroll off: while a file exists, or while
+/// anyone holds a cookie from the `Inhibit` D-Bus method. Cookies are
+/// handed back with `UnInhibit`, or when their owner leaves the bus.
+pub struct Inhibitors {
+    file: Option<FileWatch>,
+    bus: Option<BusInhibitor>,
+    inhibited: bool,
+}
+
+impl Inhibitors {
+    pub async fn new(file: &Path) -> Inhibitors {
+        let file = FileWatch::new(file)
+            .inspect_err(|e| info!("Can't watch {}: {}", file.display(), e))
+            .ok();
+        let bus = BusInhibitor::serve()
+            .await
+            .inspect_err(|e| info!("No D-Bus inhibitor: {}", e))
+            .ok();
+        let mut inhibitors = Inhibitors { file, bus, inhibited: false };
+        inhibitors.inhibited = inhibitors.check().await;
+        inhibitors
+    }
+
+    pub fn inhibited(&self) -> bool {
+        self.inhibited
+    }
+
+    /// Waits for autoscroll to become inhibited or free again, and says which.
+    pub async fn changed(&mut self) -> bool {
+        loop {
+            tokio::select! {
+                result = next_file_event(&mut self.file) => {
+                    if let Err(e) = result {
+                        error!("Stopped watching the inhibit file: {}", e);
+                        self.file = None;
+                    }
+                }
+                result = next_bus_event(&mut self.bus) => {
+                    if let Err(e) = result {
+                        error!("Stopped taking D-Bus inhibitors: {}", e);
+                        self.bus = None;
+                    }
+                }
+            }
+            let inhibited = self.check().await;
+            if inhibited != self.inhibited {
+                self.inhibited = inhibited;
+                return inhibited;
+            }
+        }
+    }
+
+    async fn check(&self) -> bool {
+        let file = self.file.as_ref().is_some_and(|file| file.path.exists());
+        let bus = match &self.bus {
+            Some(bus) => bus.held().await,
+            None => false,
+        };
+        file || bus
+    }
+}
+
+/// Watches the inhibit file's directory with inotify.
+struct FileWatch {
+    path: PathBuf,
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl FileWatch {
+    fn new(path: &Path) -> io::Result<FileWatch> {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(FileWatch { path: path.to_path_buf(), fd: AsyncFd::new(fd)? })
+    }
+
+    /// Waits for anything to change in the directory.
+    async fn changed(&mut self) -> io::Result<()> {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let mut ready = self.fd.readable().await?;
+            let read = unsafe {
+                libc::read(self.fd.get_ref().as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len())
+            };
+            if read >= 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e);
+            }
+            ready.clear_ready();
+        }
+    }
+}
+
+async fn next_file_event(file: &mut Option<FileWatch>) -> io::Result<()> {
+    match file {
+        Some(file) => file.changed().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn next_bus_event(bus: &mut Option<BusInhibitor>) -> Result<(), Box<dyn Error>> {
+    match bus {
+        Some(bus) => bus.changed().await,
+        None => std::future::pending().await,
+    }
+}
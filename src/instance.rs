@@ -0,0 +1,64 @@
+use evdev::Device;
+use std::fs::{DirBuilder, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+
+/// Where this instance keeps its socket and inhibit file: the user's
+/// runtime directory, so every user's instance gets its own.
+pub fn runtime_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir);
+    }
+    let uid = unsafe { libc::geteuid() };
+    let user_dir = PathBuf::from(format!("/run/user/{}", uid));
+    if uid == 0 {
+        PathBuf::from("/run")
+    } else if user_dir.is_dir() {
+        user_dir
+    } else {
+        // Private, so other users can't reach this instance's socket
+        let dir = std::env::temp_dir().join(format!("autoscroll-{}", uid));
+        let _ = DirBuilder::new().mode(0o700).create(&dir);
+        dir
+    }
+}
+
+/// Tells one user's virtual devices from another's; none for root, whose
+/// instance is the system-wide one.
+pub fn user_tag() -> Option<String> {
+    let uid = unsafe { libc::geteuid() };
+    if uid == 0 {
+        return None;
+    }
+    Some(std::env::var("USER").unwrap_or_else(|_| uid.to_string()))
+}
+
+/// Takes the same lock on one of our own virtual devices, so another
+/// user's instance leaves it alone even when it's named like a real mouse.
+/// The lock lasts as long as the returned file is open.
+pub fn lock(path: &Path) -> io::Result<File> {
+    let file = File::open(path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Opens an input device and takes an exclusive lock on it, or returns
+/// `None` when another instance (another user's, or a system-wide one)
+/// holds it already. The lock goes away with the device.
+pub fn claim(path: &str) -> io::Result<Option<Device>> {
+    let device = Device::open(Path::new(path))?;
+    if unsafe { libc::flock(device.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(Some(device));
+    }
+    let e = io::Error::last_os_error();
+    if e.kind() == io::ErrorKind::WouldBlock {
+        info!("{} is used by another autoscroll instance, leaving it alone", path);
+        Ok(None)
+    } else {
+        Err(e)
+    }
+}
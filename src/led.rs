@@ -0,0 +1,58 @@
+use crate::discovery;
+use evdev::{Device, EventType, InputEvent, LedType, Synchronization};
+
+/// Lights the Scroll Lock LED on a seat's keyboards while scrolling, as a
+/// status indicator that works without any help from the desktop.
+pub struct ScrollLockLed {
+    keyboards: Vec<Keyboard>,
+}
+
+struct Keyboard {
+    device: Device,
+    /// The LED's state before we took it over, restored afterwards.
+    was_on: bool,
+}
+
+impl ScrollLockLed {
+    pub fn for_seat(seat: &str) -> ScrollLockLed {
+        let keyboards = discovery::find_scroll_lock_keyboards(seat)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|device| Keyboard { device, was_on: false })
+            .collect::<Vec<_>>();
+        if keyboards.is_empty() {
+            info!("No keyboard with a Scroll Lock LED found for {}", seat);
+        }
+        ScrollLockLed { keyboards }
+    }
+
+    pub fn on(&mut self) {
+        for keyboard in &mut self.keyboards {
+            keyboard.was_on = keyboard
+                .device
+                .get_led_state()
+                .is_ok_and(|leds| leds.contains(LedType::LED_SCROLLL));
+            set(&mut keyboard.device, true);
+        }
+    }
+
+    pub fn off(&mut self) {
+        for keyboard in &mut self.keyboards {
+            set(&mut keyboard.device, keyboard.was_on);
+        }
+    }
+}
+
+fn set(device: &mut Device, on: bool) {
+    let events = [
+        InputEvent::new(EventType::LED, LedType::LED_SCROLLL.0, on as i32),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0),
+    ];
+    if let Err(e) = device.send_events(&events) {
+        debug!(
+            "Can't set Scroll Lock LED on {}: {}",
+            device.name().unwrap_or("Unknown"),
+            e
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Info,
+    Debug,
+    /// Also prints every input event the readers process.
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(LogLevel::Off),
+            "error" => Ok(LogLevel::Error),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!("unknown log level: {}", s)),
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Lines waiting for the logging thread; more than this and new ones are dropped.
+const QUEUE_LENGTH: usize = 1024;
+/// Debug and trace lines allowed per second, so -vv can't flood the terminal.
+const VERBOSE_LINES_PER_SECOND: u64 = 500;
+
+struct Line {
+    level: LogLevel,
+    text: String,
+}
+
+struct Logger {
+    tx: SyncSender<Line>,
+    thread: JoinHandle<()>,
+}
+
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static VERBOSE_SECOND: AtomicU64 = AtomicU64::new(0);
+static VERBOSE_LINES: AtomicU64 = AtomicU64::new(0);
+
+/// Moves writing to the terminal onto a thread of its own, so a slow
+/// terminal or journal never holds up input handling. Until this is
+/// called, and after `finish`, lines are written directly.
+pub fn start() {
+    let (tx, rx) = sync_channel(QUEUE_LENGTH);
+    let thread = thread::Builder::new().name("log".to_string()).spawn(move || write_lines(rx));
+    if let Ok(thread) = thread {
+        *LOGGER.lock().unwrap() = Some(Logger { tx, thread });
+    }
+}
+
+/// Writes out whatever is still queued and goes back to writing directly.
+pub fn finish() {
+    let logger = LOGGER.lock().unwrap().take();
+    if let Some(Logger { tx, thread }) = logger {
+        drop(tx);
+        let _ = thread.join();
+    }
+}
+
+fn write_lines(rx: Receiver<Line>) {
+    for line in rx {
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            print_line(LogLevel::Info, &format!("({} log lines dropped)", dropped));
+        }
+        print_line(line.level, &line.text);
+    }
+}
+
+fn print_line(level: LogLevel, text: &str) {
+    if level == LogLevel::Error {
+        let _ = writeln!(std::io::stderr(), "{}", text);
+    } else {
+        let _ = writeln!(std::io::stdout(), "{}", text);
+    }
+}
+
+/// Whether a debug or trace line fits in this second's allowance.
+fn within_rate(level: LogLevel) -> bool {
+    if level < LogLevel::Debug {
+        return true;
+    }
+    let second = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    if VERBOSE_SECOND.swap(second, Ordering::Relaxed) != second {
+        VERBOSE_LINES.store(0, Ordering::Relaxed);
+    }
+    VERBOSE_LINES.fetch_add(1, Ordering::Relaxed) < VERBOSE_LINES_PER_SECOND
+}
+
+/// Used by the logging macros; never blocks once `start` has been called.
+pub fn write(level: LogLevel, args: fmt::Arguments) {
+    if !within_rate(level) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let logger = LOGGER.lock().unwrap();
+    let Some(logger) = logger.as_ref() else {
+        drop(logger);
+        print_line(level, &args.to_string());
+        return;
+    };
+    let line = Line { level, text: args.to_string() };
+    if logger.tx.try_send(line).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Error) {
+            $crate::log::write($crate::log::LogLevel::Error, format_args!($($arg)*));
+        }
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Info) {
+            $crate::log::write($crate::log::LogLevel::Info, format_args!($($arg)*));
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Debug) {
+            $crate::log::write($crate::log::LogLevel::Debug, format_args!($($arg)*));
+        }
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Trace) {
+            $crate::log::write($crate::log::LogLevel::Trace, format_args!($($arg)*));
+        }
+    };
+}
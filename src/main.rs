@@ -1,207 +1,775 @@
-use evdev::{Device, InputEventKind, Key, RelativeAxisType};
+#[macro_use]
+mod log;
+#[macro_use]
+mod status;
+
+mod absolute;
+#[cfg_attr(not(feature = "dbus"), path = "disabled/bus_inhibit.rs")]
+mod bus_inhibit;
+mod cli;
+mod config;
+mod control;
+mod diagnose;
+mod discovery;
+mod features;
+mod handedness;
+mod hooks;
+mod inhibit;
+mod instance;
+mod led;
+mod mouse;
+#[cfg_attr(not(feature = "x11"), path = "disabled/osd.rs")]
+mod osd;
+mod output;
+mod passthrough;
+mod persist;
+#[cfg_attr(not(feature = "wayland"), path = "disabled/portal.rs")]
+mod portal;
+mod power;
+mod preset;
+mod preview;
+mod priority;
+mod screen;
+#[cfg_attr(not(feature = "dbus"), path = "disabled/session.rs")]
+mod session;
+mod scroll;
+mod selftest;
+mod speed;
+mod state;
+mod supervisor;
+mod switch;
+mod touchpad;
+#[cfg_attr(not(feature = "dbus"), path = "disabled/upower.rs")]
+mod upower;
+mod wheel;
+#[cfg_attr(not(feature = "x11"), path = "disabled/window.rs")]
+mod window;
+#[cfg_attr(not(feature = "x11"), path = "disabled/xserver.rs")]
+mod xserver;
+
+use cli::{Args, Command};
+use config::{Config, DistanceUnit, PowerSaveWhen, SessionPause};
+use control::{Control, Request};
+use log::LogLevel;
+use discovery::{find_input_devices, seat_of, Hotplug, InputDevices};
+use evdev::Key;
+use led::ScrollLockLed;
+use osd::Osd;
+use output::Output;
+use inhibit::Inhibitors;
+use persist::SavedState;
+use upower::PowerWatch;
+use session::SessionWatch;
+use scroll::{scroll_task, PointerId, PointerSender, ScrollCommand, CONTROL_POINTER};
+use speed::SpeedCurve;
+use supervisor::Supervisor;
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
+use std::future::Future;
 use std::io;
-use std::sync::mpsc::{channel};
-use std::thread;
-use std::time::{Duration, Instant};
-use uinput::event::relative::Wheel;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::{self, AbortHandle, JoinSet};
+use tokio::time::{self, Instant};
+
+type ReaderResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// How often to rescan while waiting, in case udev events can't be received.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Everything runs as tasks on one thread: a reader per input device and a
+/// scroll task per seat, woken only by input or by a due scroll tick.
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let result = run(Args::parse()).await;
+    log::finish();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Printed with Display so multi-line hints stay readable
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    // Commands print their results, so keep the usual chatter out of the way
+    if let Some(command) = &args.command {
+        log::set_level(LogLevel::Error);
+        return run_command(command, &args).await;
+    }
 
-const DEADZONE: f32 = 50.0;
-const BASE_SCROLL_SPEED: f32 = 0.05;
-const MAX_SCROLL_SPEED: i32 = 5;
+    info!("Starting autoscroll program...");
+
+    let mut config = Config::load()?;
+    // Saved state goes under the environment and arguments, which are given
+    // on purpose for this start
+    let mut saved = SavedState::default();
+    if args.restore_state {
+        saved = SavedState::load();
+        info!("Restoring state: {:?}", saved);
+        if let Some(preset) = saved.preset {
+            preset.apply(&mut config);
+        }
+        if saved.device.is_some() {
+            config.pin_device = saved.device.clone();
+        }
+    }
+    config.apply_env()?;
+    args.apply(&mut config);
+    log::set_level(config.log_level);
+    log::start();
+    priority::apply(&config.priority);
+    features::warn_missing(&config);
+    resolve_distance_unit(&mut config);
+    if config.left_handed.is_none() {
+        config.left_handed = handedness::detect();
+    }
+    if config.left_handed == Some(true) {
+        info!("Left-handed button mapping");
+    }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    println!("Starting autoscroll program...");
+    let (mut power, power_save) = match config.power_save.when {
+        PowerSaveWhen::Never => (None, false),
+        PowerSaveWhen::Always => (None, true),
+        PowerSaveWhen::Battery => match PowerWatch::connect().await {
+            Ok((watch, on_battery)) => (Some(watch), on_battery),
+            Err(e) => {
+                info!("Can't ask UPower about the battery: {}", e);
+                (None, power::on_battery())
+            }
+        },
+    };
+    if power_save {
+        info!("Saving power");
+    }
 
-    let mouse_path = find_mouse_device()?;
-    println!("Opening mouse device: {}", mouse_path);
-    let mut input = Device::open(&mouse_path)?;
+    let devices = wait_for_devices(&config, power_save).await?;
+    let mut seats = Seats::new(&config);
+    seats.power_save(power_save);
+    let mut readers = Readers::default();
+    readers.open(devices, &config, &mut seats).await?;
+
+    // Keep watching for mice that appear later, e.g. after a KVM switch
+    let mut hotplug = Hotplug::new()
+        .inspect_err(|e| info!("Can't watch for new devices: {}", e))
+        .ok();
+
+    let socket = config.control_socket.clone().unwrap_or_else(control::default_socket);
+    let mut control = Control::bind(&socket)
+        .inspect_err(|e| info!("Control socket unavailable: {}", e))
+        .ok();
+
+    let mut session = match config.session_pause {
+        SessionPause::Never => None,
+        pause => SessionWatch::connect(pause)
+            .await
+            .inspect_err(|e| info!("Not following the logind session: {}", e))
+            .ok(),
+    };
+    let inhibit_file = config.inhibit_file.clone().unwrap_or_else(inhibit::default_file);
+    let mut inhibitors = Inhibitors::new(&inhibit_file).await;
+
+    let mut pause = PauseReasons {
+        requested: saved.paused,
+        session: session.as_ref().is_some_and(SessionWatch::paused),
+        inhibited: inhibitors.inhibited(),
+    };
+    seats.pause(pause.any());
+
+    info!("Monitoring input events (devices will work normally)");
+    info!("Ready! Press the trigger button to scroll.");
+
+    let started = Instant::now();
+    let diagnose_until = args.diagnose.map(|secs| started + Duration::from_secs(secs));
+    if let Some(secs) = args.diagnose {
+        println!("Collecting diagnostics for {}s, use the mouse as usual...", secs);
+        diagnose::start();
+    }
 
-    println!("Monitoring mouse events (mouse will work normally)");
+    let mut supervisor = Supervisor::new();
+    let mut sigterm = signal(SignalKind::terminate())?;
+    while hotplug.is_some() || !readers.paths.is_empty() || supervisor.pending() {
+        tokio::select! {
+            Some(done) = readers.tasks.join_next_with_id() => match done {
+                Ok((id, ())) => {
+                    readers.finished(id);
+                }
+                Err(e) => {
+                    if let Some(path) = readers.finished(e.id())
+                        && e.is_panic()
+                    {
+                        supervisor.crashed(&format!("Reader for {} crashed", path));
+                    }
+                }
+            },
+            // Scroll tasks only end early, when their output fails or panics
+            Some(done) = seats.tasks.join_next_with_id() => {
+                let (id, panicked) = match done {
+                    Ok((id, ())) => (id, false),
+                    Err(e) => (e.id(), e.is_panic()),
+                };
+                if let Some(seat) = seats.finished(id) {
+                    readers.stop_seat(&seat);
+                    let how = if panicked { "crashed" } else { "stopped" };
+                    supervisor.crashed(&format!("Scroll task for {} {}", seat, how));
+                }
+            }
+            _ = supervisor.due() => {
+                let opened = match find_input_devices(&config) {
+                    Ok(devices) => readers.open(devices, &config, &mut seats).await,
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = opened {
+                    supervisor.crashed(&format!("Restarting failed: {}", e));
+                }
+            }
+            added = device_added(&mut hotplug) => {
+                if let Err(e) = added {
+                    error!("Stopped watching for new devices: {}", e);
+                    hotplug = None;
+                } else if let Ok(devices) = find_input_devices(&config)
+                    && let Err(e) = readers.open(devices, &config, &mut seats).await
+                {
+                    error!("Can't open new device: {}", e);
+                }
+            }
+            request = control_request(&mut control) => match request {
+                Request::Scroll { seat, command, reply } => match seats.senders.get(&seat) {
+                    Some(tx) => {
+                        let _ = tx.send((CONTROL_POINTER, command));
+                    }
+                    None => {
+                        info!("Ignoring control request for unknown seat {}", seat);
+                        let _ = reply.send(Err(format!("no such seat: {}", seat)));
+                    }
+                },
+                Request::Pause(paused) => {
+                    info!("{}", if paused { "Paused" } else { "Resumed" });
+                    pause.requested = paused;
+                    seats.pause(pause.any());
+                    saved.paused = paused;
+                    saved.save();
+                }
+                Request::Preset(preset, reply) => {
+                    let mut changed = config.clone();
+                    preset.apply(&mut changed);
+                    let result = readers.reopen(&mut config, changed, &mut seats).await;
+                    if result.is_ok() {
+                        info!("Switched to the {:?} preset", preset);
+                        saved.preset = Some(preset);
+                        saved.save();
+                    }
+                    let _ = reply.send(result);
+                }
+                Request::Watch => report_status(&readers, &config, pause),
+                Request::Device(device, reply) => {
+                    let mut changed = config.clone();
+                    changed.pin_device = device.clone();
+                    let result = readers.reopen(&mut config, changed, &mut seats).await;
+                    if result.is_ok() {
+                        info!("Now using {}", device.as_deref().unwrap_or("any mouse"));
+                        saved.device = device;
+                        saved.save();
+                    }
+                    let _ = reply.send(result);
+                }
+            },
+            changed = power_change(&mut power) => match changed {
+                Ok(on_battery) => {
+                    info!("{}", if on_battery { "On battery, saving power" } else { "Plugged in" });
+                    seats.power_save(on_battery);
+                }
+                Err(e) => {
+                    error!("Stopped following the power supply: {}", e);
+                    power = None;
+                }
+            },
+            inhibited = inhibitors.changed() => {
+                info!("{}", if inhibited { "Inhibited" } else { "No longer inhibited" });
+                pause.inhibited = inhibited;
+                seats.pause(pause.any());
+            }
+            changed = session_change(&mut session) => match changed {
+                Ok(paused) => {
+                    info!("Session {}", if paused { "in the background, pausing" } else { "back, resuming" });
+                    pause.session = paused;
+                    seats.pause(pause.any());
+                }
+                Err(e) => {
+                    error!("Stopped following the logind session: {}", e);
+                    session = None;
+                    pause.session = false;
+                    seats.pause(pause.any());
+                }
+            },
+            _ = tokio::signal::ctrl_c() => {
+                info!("Interrupted, shutting down");
+                break;
+            }
+            _ = sigterm.recv() => {
+                info!("Terminated, shutting down");
+                break;
+            }
+            _ = scroll::sleep_until(diagnose_until) => break,
+        }
+    }
+    log::finish();
+    if args.diagnose.is_some() {
+        diagnose::report(started.into_std());
+    }
 
-    let mut uinput_dev = create_uinput_device()?;
-    println!("Ready! Press middle mouse button to scroll.");
+    // Stopping the readers drops their senders, which lets each scroll task
+    // finish its current tick and exit.
+    readers.tasks.shutdown().await;
+    seats.shutdown().await;
+    Ok(())
+}
 
-    let (tx, rx) = channel::<ScrollCommand>();
+async fn run_command(command: &Command, args: &Args) -> Result<(), Box<dyn Error>> {
+    let layered_config = || -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::load()?;
+        config.apply_env()?;
+        args.apply(&mut config);
+        Ok(config)
+    };
+
+    match command {
+        Command::ConfigExport(path) => {
+            let config = layered_config()?;
+            let text = config.export()?;
+            match path {
+                Some(path) => fs::write(path, text)?,
+                None => print!("{}", text),
+            }
+        }
+        Command::ConfigImport(path) => {
+            let target = Config::import(path)?;
+            println!("Imported {} into {}", path.display(), target.display());
+        }
+        Command::ListDevices => discovery::list_devices(&layered_config()?)?,
+        Command::Features => features::print(),
+        Command::SelfTest => selftest::run(&layered_config()?).await?,
+        Command::Curve { device, plot } => {
+            let mut config = layered_config()?;
+            resolve_distance_unit(&mut config);
+            preview::print_curve(&config, device.as_deref(), *plot);
+        }
+        Command::Control(line) => {
+            let config = layered_config()?;
+            let socket = config.control_socket.unwrap_or_else(control::default_socket);
+            let reply = control::request(&socket, line)?;
+            if let Some(e) = reply.strip_prefix("error: ") {
+                return Err(e.into());
+            }
+        }
+        Command::Watch => {
+            let config = layered_config()?;
+            let socket = config.control_socket.unwrap_or_else(control::default_socket);
+            control::watch(&socket)?;
+        }
+    }
+    Ok(())
+}
 
-    thread::spawn(move || {
-        scroll_thread(&mut uinput_dev, rx);
+/// Sizes screen-relative distances for this screen, or for a 1080 pixel
+/// one when the size can't be found.
+fn resolve_distance_unit(config: &mut Config) {
+    if config.distance_unit != DistanceUnit::Screen {
+        return;
+    }
+    let height = screen::height().unwrap_or_else(|| {
+        info!("Can't find the screen size, assuming 1080 pixels high");
+        1080
     });
+    info!("Screen is {} pixels high", height);
+    config.apply_screen_height(height);
+}
 
-    let mut scrolling = false;
-    let mut origin_y = 0.0_f32;
-    let mut absolute_y = 0.0_f32;
+async fn wait_for_devices(config: &Config, power_save: bool) -> io::Result<InputDevices> {
+    let poll_interval = if power_save {
+        config.power_save.wait_poll()
+    } else {
+        WAIT_POLL_INTERVAL
+    };
+    let deadline = config
+        .wait_timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    let mut hotplug: Option<Hotplug> = None;
 
     loop {
-        for ev in input.fetch_events()?.collect::<Vec<_>>() {
-            match ev.kind() {
-                InputEventKind::Key(Key::BTN_MIDDLE) => {
-                    scrolling = ev.value() == 1;
-                    if scrolling {
-                        origin_y = absolute_y;   // mark starting Y
-                        println!("Start scroll at {}", origin_y);
-                        tx.send(ScrollCommand::Start)?;
-                    } else {
-                        println!("Stop scroll");
-                        tx.send(ScrollCommand::Stop)?;
-                    }
+        match find_input_devices(config) {
+            Ok(devices) => return Ok(devices),
+            Err(e)
+                if config.wait_for_device
+                    && e.kind() == io::ErrorKind::NotFound
+                    && deadline.is_none_or(|deadline| Instant::now() < deadline) =>
+            {
+                if hotplug.is_none() {
+                    info!("{}, waiting for one to appear...", e);
+                    hotplug = Hotplug::new()
+                        .inspect_err(|e| debug!("Can't watch for new devices: {}", e))
+                        .ok();
                 }
-                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
-                    absolute_y += ev.value() as f32;
 
-                    if scrolling {
-                        let distance = absolute_y - origin_y;
-
-                        if distance.abs() > DEADZONE {
-                            let speed =
-                                ((distance.abs() - DEADZONE) * BASE_SCROLL_SPEED)
-                                    .min(MAX_SCROLL_SPEED as f32) as i32;
-                            let speed = speed.max(1);
-
-
-                            let direction = if distance < 0.0 { 1 } else { -1 };
-                            tx.send(ScrollCommand::Update(direction * speed))?;
-                        } else {
-                            tx.send(ScrollCommand::Update(0))?;
-                        }
+                let wait_until = deadline
+                    .into_iter()
+                    .chain([Instant::now() + poll_interval])
+                    .min()
+                    .unwrap_or_else(Instant::now);
+                match hotplug.as_mut() {
+                    Some(hotplug) => {
+                        let _ = time::timeout_at(wait_until, hotplug.added()).await;
                     }
+                    None => time::sleep_until(wait_until).await,
                 }
-                _ => {}
             }
+            Err(e) => return Err(e),
         }
+    }
+}
 
-        thread::sleep(Duration::from_millis(5));
+async fn device_added(hotplug: &mut Option<Hotplug>) -> io::Result<()> {
+    match hotplug {
+        Some(hotplug) => hotplug.added().await,
+        None => std::future::pending().await,
     }
 }
 
+async fn control_request(control: &mut Option<Control>) -> Request {
+    match control {
+        Some(control) => control.next().await,
+        None => std::future::pending().await,
+    }
+}
 
-#[derive(Clone, Copy)]
-enum ScrollCommand {
-    Start,
-    Stop,
-    Update(i32),
+async fn power_change(power: &mut Option<PowerWatch>) -> Result<bool, Box<dyn Error>> {
+    match power {
+        Some(power) => power.changed().await,
+        None => std::future::pending().await,
+    }
 }
 
-fn scroll_thread(uinput_dev: &mut uinput::Device, rx: std::sync::mpsc::Receiver<ScrollCommand>) {
-    const SCROLL_INTERVAL: Duration = Duration::from_millis(50);
-    let mut last_scroll = Instant::now();
-    let mut scrolling = false;
-    let mut scroll_value = 0;
+async fn session_change(session: &mut Option<SessionWatch>) -> Result<bool, Box<dyn Error>> {
+    match session {
+        Some(session) => session.changed().await,
+        None => std::future::pending().await,
+    }
+}
 
-    loop {
-        // Check for new commands
-        while let Ok(command) = rx.try_recv() {
-            match command {
-                ScrollCommand::Start => {
-                    scrolling = true;
-                    last_scroll = Instant::now();
-                }
-                ScrollCommand::Stop => {
-                    scrolling = false;
-                    scroll_value = 0;
+/// Tells a new watcher how things stand: which devices are read and
+/// whether anything holds scrolling off.
+fn report_status(readers: &Readers, config: &Config, pause: PauseReasons) {
+    let mut paths: Vec<_> = readers.paths.iter().collect();
+    paths.sort_by_key(|(path, _)| *path);
+    for (path, reader) in paths {
+        status!("Reading {}: {}", path, reader.what);
+    }
+    if readers.paths.is_empty() {
+        status!("Not reading any device");
+    }
+    if let Some(device) = &config.pin_device {
+        status!("Only using {}", device);
+    }
+    let reasons: Vec<_> = [
+        (pause.requested, "autoscroll pause"),
+        (pause.session, "the session is in the background"),
+        (pause.inhibited, "an inhibitor is held"),
+    ]
+    .into_iter()
+    .filter_map(|(held, reason)| held.then_some(reason))
+    .collect();
+    if reasons.is_empty() {
+        status!("Not paused");
+    } else {
+        status!("Paused: {}", reasons.join(", "));
+    }
+}
+
+/// Everything that can pause scrolling; it stays paused while any holds.
+#[derive(Debug, Clone, Copy)]
+struct PauseReasons {
+    /// `autoscroll pause` on the control socket.
+    requested: bool,
+    /// The logind session is in the background.
+    session: bool,
+    /// Another program holds an inhibitor.
+    inhibited: bool,
+}
+
+impl PauseReasons {
+    fn any(self) -> bool {
+        self.requested || self.session || self.inhibited
+    }
+}
+
+/// One reader task per open input device.
+#[derive(Default)]
+struct Readers {
+    tasks: JoinSet<()>,
+    paths: HashMap<String, Reader>,
+}
+
+/// Logs why a device is skipped, handing the error back in case it was the
+/// only one.
+fn skipped(path: &str, e: Box<dyn Error>) -> Box<dyn Error> {
+    error!("Can't open {}: {}", path, e);
+    e
+}
+
+struct Reader {
+    /// What the device is, for `watch`.
+    what: String,
+    task: AbortHandle,
+}
+
+impl Readers {
+    /// Starts reading every device that isn't being read already. A device
+    /// that can't be opened is skipped; it's only an error when that leaves
+    /// nothing to read.
+    async fn open(
+        &mut self,
+        devices: InputDevices,
+        config: &Config,
+        seats: &mut Seats,
+    ) -> Result<(), Box<dyn Error>> {
+        let InputDevices { mice, touchpads, absolute: absolute_devices } = devices;
+        let mut failed = None;
+
+        for path in mice {
+            if self.paths.contains_key(&path) {
+                continue;
+            }
+            let device = match instance::claim(&path) {
+                Ok(Some(device)) => device,
+                Ok(None) => continue,
+                Err(e) => {
+                    failed = Some(skipped(&path, e.into()));
+                    continue;
                 }
-                ScrollCommand::Update(new_value) => {
-                    scroll_value = new_value;
+            };
+            let device_config = config.device(device.name().unwrap_or(""));
+            info!(
+                "Opening mouse device: {} (mode: {:?}, speed source: {:?})",
+                path, device_config.mode, device_config.speed_source
+            );
+            let what = format!(
+                "mouse {}, trigger {:?}",
+                device.name().unwrap_or("Unknown"),
+                device_config.trigger.unwrap_or(Key::BTN_MIDDLE)
+            );
+            let settings = mouse::Settings::new(config, device_config);
+            let tx = match seats.sender(&seat_of(&path)).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    failed = Some(skipped(&path, e));
+                    continue;
                 }
-            }
+            };
+            self.spawn(path, what, mouse::run(device, settings, tx));
         }
-
-        // Perform scrolling if active
-        if scrolling && last_scroll.elapsed() >= SCROLL_INTERVAL && scroll_value != 0 {
-            if let Err(e) = uinput_dev.send(Wheel::Vertical, scroll_value) {
-                eprintln!("Failed to send scroll event: {}", e);
-                break;
+        for path in touchpads {
+            if self.paths.contains_key(&path) {
+                continue;
             }
-            if let Err(e) = uinput_dev.synchronize() {
-                eprintln!("Failed to synchronize uinput device: {}", e);
-                break;
+            info!("Opening touchpad device: {}", path);
+            let device = match instance::claim(&path) {
+                Ok(Some(device)) => device,
+                Ok(None) => continue,
+                Err(e) => {
+                    failed = Some(skipped(&path, e.into()));
+                    continue;
+                }
+            };
+            let curve = SpeedCurve::new(config, &config.device(device.name().unwrap_or("")));
+            let what = format!("touchpad {}", device.name().unwrap_or("Unknown"));
+            let settings = config.touchpad.clone();
+            let tx = match seats.sender(&seat_of(&path)).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    failed = Some(skipped(&path, e));
+                    continue;
+                }
+            };
+            self.spawn(path, what, touchpad::run(device, settings, curve, tx));
+        }
+        for path in absolute_devices {
+            if self.paths.contains_key(&path) {
+                continue;
             }
-            last_scroll = Instant::now();
+            info!("Opening absolute device: {}", path);
+            let device = match instance::claim(&path) {
+                Ok(Some(device)) => device,
+                Ok(None) => continue,
+                Err(e) => {
+                    failed = Some(skipped(&path, e.into()));
+                    continue;
+                }
+            };
+            let device_config = config.device(device.name().unwrap_or(""));
+            let curve = SpeedCurve::new(config, &device_config);
+            let trigger = device_config.trigger.unwrap_or(Key::BTN_TOUCH);
+            let what = format!("absolute {}, trigger {:?}", device.name().unwrap_or("Unknown"), trigger);
+            let tx = match seats.sender(&seat_of(&path)).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    failed = Some(skipped(&path, e));
+                    continue;
+                }
+            };
+            self.spawn(path, what, absolute::run(device, curve, trigger, tx));
+        }
+        match failed {
+            Some(e) if self.paths.is_empty() => Err(e),
+            _ => Ok(()),
         }
-
-        thread::sleep(Duration::from_millis(5));
     }
-}
 
-fn find_mouse_device() -> io::Result<String> {
-    use std::fs;
+    /// Stops every reader and starts over with `changed`, keeping the old
+    /// readers and config if no device is found under it. The error is
+    /// meant for whoever asked for the change.
+    async fn reopen(&mut self, config: &mut Config, changed: Config, seats: &mut Seats) -> Result<(), String> {
+        let devices = match find_input_devices(&changed) {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("Keeping the current devices: {}", e);
+                return Err(e.to_string());
+            }
+        };
+        self.tasks.shutdown().await;
+        self.paths.clear();
+        *config = changed;
+        seats.restart(config).await;
+        self.open(devices, config, seats).await.map_err(|e| {
+            error!("Failed to open input device: {}", e);
+            format!("failed to open input device: {}", e)
+        })
+    }
 
-    let mut mouse_candidates = Vec::new();
+    fn spawn(&mut self, path: String, what: String, run: impl Future<Output = ReaderResult> + Send + 'static) {
+        let name = path.clone();
+        let task = self.tasks.spawn(async move {
+            if let Err(e) = run.await {
+                error!("Stopped reading {}: {}", name, e);
+            }
+        });
+        self.paths.insert(path, Reader { what, task });
+    }
 
-    for entry in fs::read_dir("/dev/input")? {
-        let entry = entry?;
-        let path = entry.path();
+    /// Forgets the reader whose task ended, returning its device's path.
+    fn finished(&mut self, id: task::Id) -> Option<String> {
+        let path = self.paths.iter().find(|(_, reader)| reader.task.id() == id)?.0.clone();
+        self.paths.remove(&path);
+        Some(path)
+    }
 
-        if let Some(filename) = path.file_name() {
-            if let Some(filename_str) = filename.to_str() {
-                if filename_str.starts_with("event") {
-                    if let Ok(device) = Device::open(&path) {
-                        let has_mouse_buttons = device.supported_keys().map_or(false, |keys| {
-                            keys.contains(Key::BTN_LEFT)
-                                || keys.contains(Key::BTN_MIDDLE)
-                                || keys.contains(Key::BTN_RIGHT)
-                        });
+    /// Stops the readers on a seat whose scroll task is gone, so they're
+    /// opened again with the seat's next one.
+    fn stop_seat(&mut self, seat: &str) {
+        self.paths.retain(|path, reader| {
+            let other_seat = seat_of(path) != seat;
+            if !other_seat {
+                reader.task.abort();
+            }
+            other_seat
+        });
+    }
+}
 
-                        let has_relative_movement =
-                            device.supported_relative_axes().map_or(false, |axes| {
-                                axes.contains(RelativeAxisType::REL_X)
-                                    && axes.contains(RelativeAxisType::REL_Y)
-                            });
+/// One virtual scroll device and scroll task per logind seat, so scrolling
+/// from one seat's mouse never shows up on another seat.
+struct Seats {
+    senders: HashMap<String, UnboundedSender<(PointerId, ScrollCommand)>>,
+    /// The last id handed out; 0 is the control socket's.
+    last_pointer: PointerId,
+    /// Passed on to every scroll task, including ones started later.
+    paused: bool,
+    power_save: bool,
+    config: Config,
+    tasks: JoinSet<()>,
+    /// Which seat each scroll task is for.
+    task_seats: HashMap<task::Id, String>,
+}
 
-                        if has_mouse_buttons && has_relative_movement {
-                            let device_name = device.name().unwrap_or("Unknown");
-                            println!(
-                                "Found potential mouse device: {} ({})",
-                                path.display(),
-                                device_name
-                            );
+impl Seats {
+    fn new(config: &Config) -> Self {
+        Seats {
+            senders: HashMap::new(),
+            last_pointer: 0,
+            paused: false,
+            power_save: false,
+            config: config.clone(),
+            tasks: JoinSet::new(),
+            task_seats: HashMap::new(),
+        }
+    }
 
-                            let priority = if device_name.to_lowercase().contains("keyboard") {
-                                1
-                            } else {
-                                2
-                            };
+    /// A sender for a new pointer on `seat`, starting the seat's scroll task
+    /// if it's the first.
+    async fn sender(&mut self, seat: &str) -> Result<PointerSender, Box<dyn Error>> {
+        self.last_pointer += 1;
+        if let Some(tx) = self.senders.get(seat) {
+            return Ok(PointerSender::new(self.last_pointer, tx.clone()));
+        }
 
-                            mouse_candidates.push((
-                                priority,
-                                path.to_string_lossy().to_string(),
-                                device_name.to_string(),
-                            ));
-                        }
-                    }
-                }
-            }
+        let output = Output::new(&self.config, seat).await?;
+        let (tx, rx) = unbounded_channel();
+        let task = self.tasks.spawn(scroll_task(
+            output,
+            rx,
+            scroll::Settings::new(&self.config),
+            self.config.osd.then(|| Osd::new(self.config.max_speed)),
+            self.config.scroll_lock_led.then(|| ScrollLockLed::for_seat(seat)),
+        ));
+
+        self.task_seats.insert(task.id(), seat.to_string());
+        if self.paused {
+            let _ = tx.send((CONTROL_POINTER, ScrollCommand::Pause(true)));
+        }
+        if self.power_save {
+            let _ = tx.send((CONTROL_POINTER, ScrollCommand::PowerSave(true)));
         }
+        self.senders.insert(seat.to_string(), tx.clone());
+        Ok(PointerSender::new(self.last_pointer, tx))
     }
 
-    mouse_candidates.sort_by(|a, b| b.0.cmp(&a.0));
-
-    if let Some((_, path, name)) = mouse_candidates.first() {
-        println!("Selected mouse device: {} ({})", path, name);
-        Ok(path.clone())
-    } else {
-        Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "No mouse device found",
-        ))
+    /// Forgets the seat whose scroll task ended, returning its name.
+    fn finished(&mut self, id: task::Id) -> Option<String> {
+        let seat = self.task_seats.remove(&id)?;
+        self.senders.remove(&seat);
+        Some(seat)
     }
-}
 
-fn create_uinput_device() -> Result<uinput::Device, uinput::Error> {
-    println!("Creating uinput device...");
+    fn power_save(&mut self, on: bool) {
+        self.power_save = on;
+        for tx in self.senders.values() {
+            let _ = tx.send((CONTROL_POINTER, ScrollCommand::PowerSave(on)));
+        }
+    }
 
-    if !std::path::Path::new("/dev/uinput").exists() {
-        eprintln!(
-            "Warning: /dev/uinput does not exist. You may need to load the uinput kernel module:"
-        );
-        eprintln!("  sudo modprobe uinput");
+    fn pause(&mut self, paused: bool) {
+        self.paused = paused;
+        for tx in self.senders.values() {
+            let _ = tx.send((CONTROL_POINTER, ScrollCommand::Pause(paused)));
+        }
     }
 
-    let device = uinput::default()?
-        .name("autoscroll-device")?
-        .event(uinput::event::relative::Wheel::Vertical)?
-        .create()?;
+    /// Ends every scroll task, so each seat starts over with `config` when
+    /// its mice are opened again. Readers must be stopped first, as the
+    /// tasks run until every sender is gone.
+    async fn restart(&mut self, config: &Config) {
+        self.senders.clear();
+        while self.tasks.join_next().await.is_some() {}
+        self.task_seats.clear();
+        self.config = config.clone();
+    }
 
-    println!("Successfully created uinput device");
-    Ok(device)
-}
\ No newline at end of file
+    async fn shutdown(mut self) {
+        self.senders.clear();
+        while self.tasks.join_next().await.is_some() {}
+    }
+}
@@ -0,0 +1,371 @@
+use crate::config::{Config, DeviceConfig, Origin, PassthroughConfig, SpeedSource, WheelConfig};
+use crate::diagnose;
+use crate::passthrough::Passthrough;
+use crate::speed::{DeadzoneCalibrator, SpeedCurve, VelocityTracker};
+use crate::scroll::{PointerSender, ScrollCommand};
+use crate::state::{Point, ScrollState};
+use crate::switch::{Switch, SwitchAction};
+use crate::wheel::WheelRescaler;
+use evdev::{Device, InputEventKind, Key, RelativeAxisType, Synchronization};
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::Duration;
+
+/// With `origin = "first-motion"`, the pointer has moved once it's this
+/// many counts from where the trigger went down.
+const FIRST_MOTION: f32 = 2.0;
+
+/// Everything one mouse's reader works from, taken from the config.
+pub struct Settings {
+    pub device: DeviceConfig,
+    pub curve: SpeedCurve,
+    pub calibrator: Option<DeadzoneCalibrator>,
+    pub switch: Option<Switch>,
+    pub cooldown: Duration,
+    pub origin: Origin,
+    pub inhibit_during_drag: bool,
+    pub cancel_on_click: bool,
+    pub pointer_rail: bool,
+    pub wheel: WheelConfig,
+    pub passthrough: PassthroughConfig,
+}
+
+impl Settings {
+    pub fn new(config: &Config, device: DeviceConfig) -> Self {
+        let curve = SpeedCurve::new(config, &device);
+        Settings {
+            calibrator: device
+                .auto_deadzone
+                .unwrap_or(config.auto_deadzone)
+                .then(|| DeadzoneCalibrator::new(curve.deadzone)),
+            switch: config.switch.enabled.then(|| Switch::new(&config.switch)),
+            cooldown: Duration::from_millis(config.cooldown_ms),
+            origin: config.origin,
+            inhibit_during_drag: config.inhibit_during_drag,
+            cancel_on_click: config.cancel_on_click,
+            pointer_rail: config.pointer_rail,
+            wheel: config.wheel.clone(),
+            passthrough: config.passthrough.clone(),
+            device,
+            curve,
+        }
+    }
+}
+
+pub async fn run(
+    mut input: Device,
+    settings: Settings,
+    mut tx: PointerSender,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Settings {
+        device: device_config,
+        mut curve,
+        mut calibrator,
+        mut switch,
+        cooldown,
+        origin,
+        inhibit_during_drag,
+        cancel_on_click,
+        pointer_rail,
+        wheel,
+        passthrough: identities,
+    } = settings;
+    let trigger = device_config.trigger.unwrap_or(Key::BTN_MIDDLE);
+
+    let mut state = ScrollState::Idle;
+    // A trigger press swallowed during the cooldown, whose release goes too
+    let mut swallowed = false;
+    // Other mouse buttons currently down
+    let mut held: HashSet<Key> = HashSet::new();
+    let (mut absolute_x, mut absolute_y) = (0.0_f32, 0.0_f32);
+    // Motion since the last SYN_REPORT, so X and Y from one report are handled together
+    let (mut frame_dx, mut frame_dy) = (0, 0);
+    let mut frame_wheel = false;
+    // Tilt nudges in this scroll, and whether the last frame had one
+    let mut nudge = 0;
+    let mut frame_nudged = false;
+    // Whether this press has scrolled yet, and how far it got from the
+    // anchor, to tell a watcher why it didn't
+    let mut scrolled = false;
+    let mut farthest = 0.0_f32;
+    // Still waiting for the first motion to put the anchor at
+    let mut awaiting_origin = false;
+    let mut velocity_x = VelocityTracker::default();
+    let mut velocity_y = VelocityTracker::default();
+
+    // Only grab the mouse when its own wheel events need changing, or its
+    // motion holding to the rail
+    let mut passthrough = if wheel.enabled() || pointer_rail {
+        Some(Passthrough::grab(&mut input, &identities)?)
+    } else {
+        None
+    };
+    let mut rescaler = WheelRescaler::new(&wheel);
+
+    let name = input.name().unwrap_or("Unknown").to_string();
+    let mut events = input.into_event_stream()?;
+    loop {
+        let ev = events.next_event().await?;
+        trace!("{}: {:?} {}", name, ev.kind(), ev.value());
+        diagnose::input_event(ev.timestamp());
+        // Keep the trigger bouncing and the hand settling after a scroll
+        // from reaching the desktop as a click or a nudge
+        let residual = match ev.kind() {
+            InputEventKind::Key(key) if key == trigger => match ev.value() {
+                1 => {
+                    swallowed = state.in_cooldown(ev.timestamp());
+                    swallowed
+                }
+                // A keyboard key as the trigger repeats while held
+                2 => swallowed,
+                _ => std::mem::take(&mut swallowed),
+            },
+            InputEventKind::RelAxis(axis @ (RelativeAxisType::REL_X | RelativeAxisType::REL_Y)) => {
+                state.in_cooldown(ev.timestamp())
+                    || (pointer_rail && off_rail(axis, state.anchor(), (absolute_x, absolute_y), curve.horizontal))
+            }
+            // Tilts that nudge the speed don't also scroll sideways
+            InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL | RelativeAxisType::REL_HWHEEL_HI_RES) => {
+                wheel.tilt_nudge && state.anchor().is_some()
+            }
+            _ => false,
+        };
+        if let Some(passthrough) = &mut passthrough {
+            if !residual && !rescaler.take(&ev) {
+                passthrough.push(ev);
+            }
+            if ev.kind() == InputEventKind::Synchronization(Synchronization::SYN_REPORT) {
+                for wheel_event in rescaler.finish_frame(ev.timestamp()) {
+                    passthrough.push(wheel_event);
+                }
+                passthrough.flush()?;
+            }
+        }
+
+        match ev.kind() {
+            InputEventKind::Key(key) if key == trigger && let Some(switch) = &mut switch => {
+                if ev.value() == 1 {
+                    switch.press(ev.timestamp());
+                } else if ev.value() == 0 {
+                    match switch.release(ev.timestamp()) {
+                        Some(SwitchAction::Start(speed)) => {
+                            trace!("Start switch scroll");
+                            tx.send(ScrollCommand::Start { device: name.clone() })?;
+                            tx.send(ScrollCommand::Update(speed))?;
+                        }
+                        Some(SwitchAction::Reverse(speed)) => {
+                            trace!("Reverse switch scroll");
+                            tx.send(ScrollCommand::Update(speed))?;
+                        }
+                        Some(SwitchAction::Stop) => {
+                            trace!("Stop switch scroll");
+                            tx.send(ScrollCommand::Stop)?;
+                        }
+                        None => {}
+                    }
+                }
+            }
+            InputEventKind::Key(key) if key == trigger => match ev.value() {
+                1 if inhibit_during_drag && !held.is_empty() => {
+                    debug!("{}: not scrolling while {:?} is held", name, held);
+                    status!("{}: trigger ignored while {:?} is held (inhibit_during_drag)", name, held);
+                }
+                1 => {
+                    if let Some((anchor_x, anchor_y)) = state.press((absolute_x, absolute_y), ev.timestamp()) {
+                        velocity_x.reset();
+                        velocity_y.reset();
+                        nudge = 0;
+                        scrolled = false;
+                        farthest = 0.0;
+                        awaiting_origin = origin == Origin::FirstMotion;
+                        if let Some(calibrator) = &mut calibrator {
+                            calibrator.press(ev.timestamp());
+                        }
+                        trace!("Start scroll at {}, {}", anchor_x, anchor_y);
+                        tx.send(ScrollCommand::Start { device: name.clone() })?;
+                    } else if state.in_cooldown(ev.timestamp()) {
+                        status!("{}: trigger ignored, it came within cooldown_ms of the last scroll", name);
+                    }
+                }
+                0 if state.release(ev.timestamp(), cooldown) => {
+                    if !scrolled {
+                        status!(
+                            "{}: released inside the deadzone, {:.0} of {:.0} counts from where it was pressed",
+                            name, farthest, curve.deadzone
+                        );
+                    }
+                    if let Some(calibrator) = &mut calibrator
+                        && let Some(deadzone) = calibrator.release(ev.timestamp())
+                    {
+                        calibrate(&name, &mut curve, deadzone);
+                    }
+                    trace!("Stop scroll");
+                    tx.send(ScrollCommand::Stop)?;
+                }
+                _ => {}
+            },
+            InputEventKind::Key(key) if is_button(key) => {
+                if ev.value() == 0 {
+                    held.remove(&key);
+                } else {
+                    held.insert(key);
+                }
+                if ev.value() == 1 && cancel_on_click && state.anchor().is_some() {
+                    // No cooldown, so the click and whatever follows go through
+                    state.release(ev.timestamp(), Duration::ZERO);
+                    trace!("{:?} cancels the scroll", key);
+                    tx.send(ScrollCommand::Stop)?;
+                } else if ev.value() == 1 && cancel_on_click && let Some(switch) = &mut switch && switch.cancel() {
+                    trace!("{:?} cancels the switch scroll", key);
+                    tx.send(ScrollCommand::Stop)?;
+                } else if ev.value() == 1 && key != Key::BTN_LEFT && key != Key::BTN_RIGHT {
+                    status!("{}: {:?} pressed, but the trigger here is {:?}", name, key, trigger);
+                }
+            }
+            // The keyboard half of a combo receiver only passes through, so
+            // Ctrl or Shift held on it still change what the scrolling does
+            InputEventKind::Key(_) => {}
+            InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
+                absolute_x += ev.value() as f32;
+                frame_dx += ev.value();
+            }
+            InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
+                absolute_y += ev.value() as f32;
+                frame_dy += ev.value();
+            }
+            InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL)
+                if wheel.tilt_nudge && state.anchor().is_some() =>
+            {
+                nudge += ev.value().signum();
+                frame_nudged = true;
+            }
+            InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL_HI_RES)
+                if wheel.tilt_nudge && state.anchor().is_some() => {}
+            InputEventKind::RelAxis(
+                RelativeAxisType::REL_WHEEL
+                | RelativeAxisType::REL_HWHEEL
+                | RelativeAxisType::REL_WHEEL_HI_RES
+                | RelativeAxisType::REL_HWHEEL_HI_RES,
+            ) => frame_wheel = true,
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                if frame_wheel {
+                    tx.send(ScrollCommand::Wheel)?;
+                    frame_wheel = false;
+                }
+                state.settle(ev.timestamp());
+                if awaiting_origin
+                    && let Some((anchor_x, anchor_y)) = state.anchor()
+                    && (absolute_x - anchor_x).hypot(absolute_y - anchor_y) >= FIRST_MOTION
+                {
+                    trace!("Anchor moved to {}, {}", absolute_x, absolute_y);
+                    state.reanchor((absolute_x, absolute_y));
+                    awaiting_origin = false;
+                }
+                if let Some((anchor_x, anchor_y)) = state.anchor()
+                    && (frame_dx != 0 || frame_dy != 0 || frame_nudged)
+                {
+                    let (dx, dy) = (absolute_x - anchor_x, absolute_y - anchor_y);
+                    farthest = farthest.max(dx.hypot(dy));
+                    if let Some(calibrator) = &mut calibrator
+                        && let Some(deadzone) = calibrator.motion(dx.hypot(dy), ev.timestamp(), curve.deadzone)
+                    {
+                        calibrate(&name, &mut curve, deadzone);
+                    }
+                    let command = match curve.pan(frame_dx as f32, frame_dy as f32) {
+                        Some((vertical, horizontal)) => Some(ScrollCommand::Pan { vertical, horizontal }),
+                        None => match device_config.speed_source {
+                            SpeedSource::Displacement => {
+                                let speed = curve.scroll(dx, dy).nudge(nudge);
+                                state.set_speed(speed).then_some(ScrollCommand::Update(speed))
+                            }
+                            // Sent on every frame, since each one keeps the impulse alive
+                            SpeedSource::Velocity => {
+                                let dx = velocity_x.update(frame_dx, ev.timestamp());
+                                let dy = velocity_y.update(frame_dy, ev.timestamp());
+                                let speed = curve.scroll(dx, dy).nudge(nudge);
+                                state.set_speed(speed);
+                                Some(ScrollCommand::Impulse(speed))
+                            }
+                        },
+                    };
+                    if let Some(command) = command {
+                        scrolled |= match &command {
+                            ScrollCommand::Update(speed) | ScrollCommand::Impulse(speed) => !speed.is_zero(),
+                            _ => true,
+                        };
+                        tx.send(command)?;
+                    }
+                }
+                frame_dx = 0;
+                frame_dy = 0;
+                frame_nudged = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether motion along `axis` would take the pointer off the line through
+/// the anchor, if there is one. That's the vertical line, unless horizontal
+/// scrolling is on and the pointer has gone further sideways than up or
+/// down.
+fn off_rail(axis: RelativeAxisType, anchor: Option<Point>, position: Point, horizontal: bool) -> bool {
+    let Some((anchor_x, anchor_y)) = anchor else {
+        return false;
+    };
+    let sideways = horizontal && (position.0 - anchor_x).abs() > (position.1 - anchor_y).abs();
+    axis == if sideways { RelativeAxisType::REL_Y } else { RelativeAxisType::REL_X }
+}
+
+/// Mouse buttons, from BTN_LEFT to BTN_TASK.
+fn is_button(key: Key) -> bool {
+    (Key::BTN_LEFT.code()..=Key::BTN_TASK.code()).contains(&key.code())
+}
+
+fn calibrate(name: &str, curve: &mut SpeedCurve, deadzone: f32) {
+    if (deadzone - curve.deadzone).abs() >= 1.0 {
+        debug!("{}: deadzone now {:.0}", name, deadzone);
+    }
+    curve.set_deadzone(deadzone);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REL_X: RelativeAxisType = RelativeAxisType::REL_X;
+    const REL_Y: RelativeAxisType = RelativeAxisType::REL_Y;
+
+    const ANCHOR: Option<Point> = Some((100.0, 100.0));
+
+    #[test]
+    fn rail_while_scrolling_up_and_down() {
+        let below = (103.0, 160.0);
+        assert!(!off_rail(REL_Y, ANCHOR, below, false));
+        assert!(off_rail(REL_X, ANCHOR, below, false));
+        // Without horizontal scrolling the rail stays vertical however far
+        // sideways the pointer is
+        assert!(off_rail(REL_X, ANCHOR, (300.0, 101.0), false));
+    }
+
+    #[test]
+    fn rail_while_scrolling_sideways() {
+        let right = (160.0, 103.0);
+        assert!(!off_rail(REL_X, ANCHOR, right, true));
+        assert!(off_rail(REL_Y, ANCHOR, right, true));
+    }
+
+    #[test]
+    fn rail_turns_past_the_diagonal() {
+        // Level with the diagonal the rail is still vertical
+        assert!(off_rail(REL_X, ANCHOR, (140.0, 140.0), true));
+        assert!(!off_rail(REL_X, ANCHOR, (141.0, 140.0), true));
+        assert!(off_rail(REL_Y, ANCHOR, (141.0, 140.0), true));
+    }
+
+    #[test]
+    fn no_rail_without_a_scroll() {
+        assert!(!off_rail(REL_X, None, (160.0, 103.0), false));
+        assert!(!off_rail(REL_Y, None, (160.0, 103.0), true));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::speed::ScrollSpeed;
+use std::error::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ConfigureWindowAux, ConnectionExt, CoordMode, CreateGCAux, CreateWindowAux, Gcontext, Point,
+    PolyShape, Rectangle, StackMode, Window, WindowClass,
+};
+use x11rb::rust_connection::RustConnection;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+const SIZE: i16 = 48;
+const CENTER: f32 = SIZE as f32 / 2.0;
+
+struct Overlay {
+    conn: RustConnection,
+    root: Window,
+    window: Window,
+    gc: Gcontext,
+}
+
+impl Overlay {
+    fn create() -> Result<Overlay, Box<dyn Error>> {
+        let (conn, screen) = x11rb::connect(None)?;
+        let screen = &conn.setup().roots[screen];
+        let (root, black, white) = (screen.root, screen.black_pixel, screen.white_pixel);
+
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            SIZE as u16,
+            SIZE as u16,
+            1,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .background_pixel(black)
+                .border_pixel(white),
+        )?;
+        let gc = conn.generate_id()?;
+        conn.create_gc(gc, window, &CreateGCAux::new().foreground(white))?;
+        conn.flush()?;
+
+        Ok(Overlay { conn, root, window, gc })
+    }
+
+    /// Centres the indicator on the pointer, i.e. on the scroll anchor.
+    fn show(&self) -> Result<(), Box<dyn Error>> {
+        let pointer = self.conn.query_pointer(self.root)?.reply()?;
+        self.conn.configure_window(
+            self.window,
+            &ConfigureWindowAux::new()
+                .x((pointer.root_x - SIZE / 2) as i32)
+                .y((pointer.root_y - SIZE / 2) as i32)
+                .stack_mode(StackMode::ABOVE),
+        )?;
+        self.conn.map_window(self.window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn draw(&self, speed: ScrollSpeed, max_speed: i32) -> Result<(), Box<dyn Error>> {
+        self.conn.clear_area(false, self.window, 0, 0, 0, 0)?;
+        let dot = Rectangle { x: SIZE / 2 - 2, y: SIZE / 2 - 2, width: 4, height: 4 };
+        self.conn.poly_fill_rectangle(self.window, self.gc, &[dot])?;
+
+        // Positive vertical scrolls up, so it points up the screen
+        for (clicks, direction) in [
+            (speed.vertical, (0.0, -1.0)),
+            (speed.horizontal, (1.0, 0.0)),
+        ] {
+            if clicks != 0 {
+                let tier = (clicks.abs() as f32 / max_speed.max(1) as f32).min(1.0);
+                let sign = clicks.signum() as f32;
+                let points = arrow((direction.0 * sign, direction.1 * sign), tier);
+                self.conn
+                    .fill_poly(self.window, self.gc, PolyShape::CONVEX, CoordMode::ORIGIN, &points)?;
+            }
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn hide(&self) -> Result<(), Box<dyn Error>> {
+        self.conn.unmap_window(self.window)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// A triangle pointing along `direction` that grows longer as the speed
+/// approaches `max_speed`.
+fn arrow(direction: (f32, f32), tier: f32) -> [Point; 3] {
+    let (ux, uy) = direction;
+    let tip = 8.0 + 14.0 * tier;
+    let base = 5.0;
+    let half_width = 5.0 + 2.0 * tier;
+    let point = |along: f32, across: f32| Point {
+        x: (CENTER + ux * along - uy * across) as i16,
+        y: (CENTER + uy * along + ux * across) as i16,
+    };
+    [point(tip, 0.0), point(base, half_width), point(base, -half_width)]
+}
+
+/// A small X11 window at the scroll anchor showing which way and how fast
+/// we're scrolling, like the arrow Windows draws. Connects lazily and
+/// reconnects after errors, like `WindowTracker`.
+pub struct Osd {
+    overlay: Option<Overlay>,
+    max_speed: i32,
+    shown: Option<ScrollSpeed>,
+}
+
+impl Osd {
+    pub fn new(max_speed: i32) -> Osd {
+        Osd { overlay: None, max_speed, shown: None }
+    }
+
+    pub fn show(&mut self) {
+        self.shown = None;
+        self.with_overlay(|overlay| overlay.show());
+        self.update(ScrollSpeed::default());
+    }
+
+    pub fn update(&mut self, speed: ScrollSpeed) {
+        if self.shown == Some(speed) {
+            return;
+        }
+        self.shown = Some(speed);
+        let max_speed = self.max_speed;
+        self.with_overlay(|overlay| overlay.draw(speed, max_speed));
+    }
+
+    pub fn hide(&mut self) {
+        self.shown = None;
+        if let Some(overlay) = &self.overlay
+            && let Err(e) = overlay.hide()
+        {
+            debug!("Lost the X11 connection for the indicator: {}", e);
+            self.overlay = None;
+        }
+    }
+
+    fn with_overlay(&mut self, action: impl FnOnce(&Overlay) -> Result<(), Box<dyn Error>>) {
+        if self.overlay.is_none() {
+            match Overlay::create() {
+                Ok(overlay) => self.overlay = Some(overlay),
+                Err(e) => {
+                    debug!("Can't show the speed indicator: {}", e);
+                    return;
+                }
+            }
+        }
+
+        if let Some(overlay) = &self.overlay
+            && let Err(e) = action(overlay)
+        {
+            debug!("Lost the X11 connection for the indicator: {}", e);
+            self.overlay = None;
+        }
+    }
+}
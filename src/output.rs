@@ -0,0 +1,276 @@
+use crate::config::{Config, OutputBackend};
+use crate::instance;
+use crate::passthrough::VIRTUAL_DEVICE_PREFIX;
+use crate::portal::RemoteDesktop;
+use crate::speed::ScrollSpeed;
+use crate::wheel::{HiResAccumulator, HI_RES_PER_NOTCH};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, EventType, InputEvent, Key, RelativeAxisType};
+use std::error::Error;
+use std::io;
+use std::mem;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+
+/// Where wheel events go: our own virtual device, a running ydotoold, or
+/// the desktop portal.
+pub struct Output {
+    backend: Backend,
+    /// Panning motion not yet sent, per axis.
+    vertical: HiResAccumulator,
+    horizontal: HiResAccumulator,
+}
+
+enum Backend {
+    Uinput(VirtualDevice),
+    /// ydotoold already owns a uinput device and reads raw `input_event`s,
+    /// one per datagram, from its socket. Its device has no hi-res wheel.
+    Ydotool(UnixDatagram),
+    /// The xdg-desktop-portal RemoteDesktop interface, for Wayland sessions
+    /// without /dev/uinput access. Input is still read from /dev/input.
+    Portal(RemoteDesktop),
+}
+
+impl Output {
+    pub async fn new(config: &Config, seat: &str) -> Result<Output, Box<dyn Error>> {
+        let backend = match config.output {
+            OutputBackend::Uinput => Backend::Uinput(create_uinput_device(seat)?),
+            OutputBackend::Ydotool => {
+                let path = config.ydotool_socket.clone().unwrap_or_else(default_ydotool_socket);
+                info!("Sending wheel events for {} to ydotoold at {}", seat, path.display());
+                let socket = UnixDatagram::unbound()?;
+                socket
+                    .connect(&path)
+                    .map_err(|e| format!("can't connect to ydotoold at {}: {}", path.display(), e))?;
+                Backend::Ydotool(socket)
+            }
+            OutputBackend::Portal => {
+                info!("Requesting a remote desktop portal session for {}...", seat);
+                Backend::Portal(RemoteDesktop::connect().await?)
+            }
+        };
+        Ok(Output {
+            backend,
+            vertical: HiResAccumulator::default(),
+            horizontal: HiResAccumulator::default(),
+        })
+    }
+
+    /// Sends one tick's worth of wheel clicks as a single report.
+    pub async fn send(&mut self, speed: ScrollSpeed) -> Result<(), Box<dyn Error>> {
+        if let Backend::Portal(portal) = &self.backend {
+            portal.scroll(speed.vertical, speed.horizontal).await?;
+            return Ok(());
+        }
+        self.emit(
+            (speed.vertical * HI_RES_PER_NOTCH, speed.vertical),
+            (speed.horizontal * HI_RES_PER_NOTCH, speed.horizontal),
+        )?;
+        Ok(())
+    }
+
+    /// Sends smooth scrolling in hi-res units (120 per notch), for panning.
+    pub async fn pan(&mut self, vertical: f32, horizontal: f32) -> Result<(), Box<dyn Error>> {
+        if let Backend::Portal(portal) = &self.backend {
+            portal.scroll_smooth(vertical, horizontal).await?;
+            return Ok(());
+        }
+        let vertical = self.vertical.add(vertical);
+        let horizontal = self.horizontal.add(horizontal);
+        self.emit(vertical, horizontal)?;
+        Ok(())
+    }
+
+    /// Pages down with Space, or up with Shift+Space. Positive `pages` go
+    /// up, following the wheel's signs.
+    pub async fn page(&mut self, pages: i32) -> Result<(), Box<dyn Error>> {
+        let mut keys = Vec::new();
+        for _ in 0..pages.abs() {
+            if pages > 0 {
+                keys.push((Key::KEY_LEFTSHIFT, true));
+            }
+            keys.extend([(Key::KEY_SPACE, true), (Key::KEY_SPACE, false)]);
+            if pages > 0 {
+                keys.push((Key::KEY_LEFTSHIFT, false));
+            }
+        }
+        match &mut self.backend {
+            Backend::Uinput(device) => {
+                for (key, pressed) in keys {
+                    device.emit(&[InputEvent::new(EventType::KEY, key.code(), pressed as i32)])?;
+                }
+            }
+            Backend::Ydotool(socket) => {
+                for (key, pressed) in keys {
+                    send_event(socket, EV_KEY, key.code(), pressed as i32)?;
+                    send_event(socket, EV_SYN, SYN_REPORT, 0)?;
+                }
+            }
+            Backend::Portal(portal) => {
+                for (key, pressed) in keys {
+                    portal.key(key.code(), pressed).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes (hi-res units, notches) for each axis as one report.
+    fn emit(&mut self, vertical: (i32, i32), horizontal: (i32, i32)) -> io::Result<()> {
+        match &mut self.backend {
+            Backend::Uinput(device) => {
+                let events: Vec<InputEvent> = [
+                    (RelativeAxisType::REL_WHEEL_HI_RES, vertical.0),
+                    (RelativeAxisType::REL_WHEEL, vertical.1),
+                    (RelativeAxisType::REL_HWHEEL_HI_RES, horizontal.0),
+                    (RelativeAxisType::REL_HWHEEL, horizontal.1),
+                ]
+                .into_iter()
+                .filter(|(_, value)| *value != 0)
+                .map(|(axis, value)| InputEvent::new(EventType::RELATIVE, axis.0, value))
+                .collect();
+                if !events.is_empty() {
+                    device.emit(&events)?;
+                }
+            }
+            Backend::Ydotool(socket) => {
+                if vertical.1 == 0 && horizontal.1 == 0 {
+                    return Ok(());
+                }
+                if vertical.1 != 0 {
+                    send_event(socket, EV_REL, REL_WHEEL, vertical.1)?;
+                }
+                if horizontal.1 != 0 {
+                    send_event(socket, EV_REL, REL_HWHEEL, horizontal.1)?;
+                }
+                send_event(socket, EV_SYN, SYN_REPORT, 0)?;
+            }
+            Backend::Portal(_) => unreachable!("portal scrolling doesn't go through evdev events"),
+        }
+        Ok(())
+    }
+}
+
+fn send_event(socket: &UnixDatagram, type_: u16, code: u16, value: i32) -> io::Result<()> {
+    // ydotoold fills in the timestamp itself
+    let event = libc::input_event {
+        time: libc::timeval { tv_sec: 0, tv_usec: 0 },
+        type_,
+        code,
+        value,
+    };
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            &event as *const libc::input_event as *const u8,
+            mem::size_of::<libc::input_event>(),
+        )
+    };
+    socket.send(bytes)?;
+    Ok(())
+}
+
+/// Same lookup as the ydotool client.
+fn default_ydotool_socket() -> PathBuf {
+    std::env::var_os("YDOTOOL_SOCKET")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp/.ydotool_socket"))
+}
+
+/// The virtual device for seat0 keeps the plain name; others get the seat
+/// appended so a udev rule can assign them to their seat by name. Per-user
+/// instances add the user name after that.
+fn create_uinput_device(seat: &str) -> io::Result<VirtualDevice> {
+    let mut name = format!("{}-device", VIRTUAL_DEVICE_PREFIX);
+    if seat != "seat0" {
+        name = format!("{}-{}", name, seat);
+    }
+    if let Some(user) = instance::user_tag() {
+        name = format!("{}-{}", name, user);
+    }
+    info!("Creating uinput device {} for {}...", name, seat);
+
+    let mut axes = AttributeSet::<RelativeAxisType>::new();
+    axes.insert(RelativeAxisType::REL_WHEEL);
+    axes.insert(RelativeAxisType::REL_HWHEEL);
+    axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+    axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+    // For apps that page with Space instead of scrolling
+    let mut keys = AttributeSet::<Key>::new();
+    keys.insert(Key::KEY_SPACE);
+    keys.insert(Key::KEY_LEFTSHIFT);
+    let device = open_uinput()?
+        .name(&name)
+        .with_relative_axes(&axes)?
+        .with_keys(&keys)?
+        .build()?;
+
+    info!("Successfully created uinput device");
+    Ok(device)
+}
+
+const UINPUT: &str = "/dev/uinput";
+
+/// Opens /dev/uinput, loading the kernel module first when it's missing
+/// and we're allowed to. Errors say exactly what to change.
+pub fn open_uinput<'a>() -> io::Result<VirtualDeviceBuilder<'a>> {
+    if !Path::new(UINPUT).exists() {
+        load_uinput_module();
+    }
+    if !Path::new(UINPUT).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} does not exist. Load the uinput kernel module with\n  \
+                 sudo modprobe uinput\n\
+                 and make it load at boot with\n  \
+                 echo uinput | sudo tee /etc/modules-load.d/uinput.conf",
+                UINPUT
+            ),
+        ));
+    }
+
+    VirtualDeviceBuilder::new().map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "no permission to open {}. Either run as root, or allow the input \
+                 group to use it with a udev rule in /etc/udev/rules.d/99-uinput.rules:\n  \
+                 KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\", OPTIONS+=\"static_node=uinput\"\n\
+                 then run\n  \
+                 sudo udevadm control --reload && sudo udevadm trigger /dev/uinput\n  \
+                 sudo usermod -aG input $USER\n\
+                 and log in again. Or use output = \"ydotool\" or \"portal\" instead.",
+                UINPUT
+            ),
+        ),
+        _ => e,
+    })
+}
+
+/// Only root can load modules, so don't bother trying otherwise.
+fn load_uinput_module() {
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    info!("{} is missing, loading the uinput module...", UINPUT);
+    match std::process::Command::new("modprobe").arg("uinput").status() {
+        Ok(status) if status.success() => {
+            // The device node shows up shortly after the module loads
+            for _ in 0..20 {
+                if Path::new(UINPUT).exists() {
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+        }
+        Ok(status) => error!("modprobe uinput {}", status),
+        Err(e) => error!("Can't run modprobe: {}", e),
+    }
+}
@@ -0,0 +1,170 @@
+use crate::config::{PassthroughConfig, VirtualIdentity};
+use crate::instance;
+use crate::output;
+use evdev::uinput::VirtualDevice;
+use evdev::{AttributeSet, Device, InputEvent, InputEventKind, InputId, RelativeAxisType, Synchronization};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Names of our own virtual devices start with this, so discovery never
+/// picks them up as input.
+pub const VIRTUAL_DEVICE_PREFIX: &str = "autoscroll";
+
+/// Event nodes of the passthrough devices we created, which discovery skips
+/// even when they're named like the real mouse.
+static OWN_NODES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+pub fn is_own(path: &Path) -> bool {
+    OWN_NODES.lock().unwrap().iter().any(|node| node == path)
+}
+
+const WHEEL_AXES: [RelativeAxisType; 4] = [
+    RelativeAxisType::REL_WHEEL,
+    RelativeAxisType::REL_WHEEL_HI_RES,
+    RelativeAxisType::REL_HWHEEL,
+    RelativeAxisType::REL_HWHEEL_HI_RES,
+];
+
+/// Takes exclusive hold of a mouse and re-emits its events through two
+/// virtual devices, so some of them can be changed on the way: a plain
+/// mouse for motion and buttons, which keeps the desktop's pointer
+/// acceleration, and a wheel-only device whose scroll settings can be set
+/// apart from it. The kernel drops the grab when the device is closed,
+/// including when the daemon dies.
+pub struct Passthrough {
+    pointer: VirtualDevice,
+    wheel: VirtualDevice,
+    pointer_frame: Vec<InputEvent>,
+    wheel_frame: Vec<InputEvent>,
+    nodes: Vec<PathBuf>,
+    _locks: Vec<File>,
+}
+
+impl Passthrough {
+    pub fn grab(input: &mut Device, identities: &PassthroughConfig) -> io::Result<Passthrough> {
+        let mouse_name = input.name().unwrap_or("mouse").to_string();
+        let pointer_name = identities.pointer.name(&format!("{} passthrough", VIRTUAL_DEVICE_PREFIX), &mouse_name);
+        let wheel_name = identities.wheel.name(&format!("{} wheel", VIRTUAL_DEVICE_PREFIX), &mouse_name);
+
+        let mut axes = AttributeSet::<RelativeAxisType>::new();
+        for axis in input.supported_relative_axes().into_iter().flat_map(|a| a.iter()) {
+            if !WHEEL_AXES.contains(&axis) {
+                axes.insert(axis);
+            }
+        }
+        let mut builder = output::open_uinput()?
+            .name(&pointer_name)
+            .input_id(identities.pointer.input_id(input.input_id()))
+            .with_relative_axes(&axes)?;
+        if let Some(keys) = input.supported_keys() {
+            builder = builder.with_keys(keys)?;
+        }
+        if let Some(misc) = input.misc_properties() {
+            builder = builder.with_msc(misc)?;
+        }
+        let mut pointer = builder.build()?;
+
+        // Hi-res wheel axes are always offered, since re-emitted scrolling
+        // may be finer than what the mouse itself reports.
+        let mut wheel_axes = AttributeSet::<RelativeAxisType>::new();
+        wheel_axes.insert(RelativeAxisType::REL_WHEEL);
+        wheel_axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+        if input
+            .supported_relative_axes()
+            .is_some_and(|axes| axes.contains(RelativeAxisType::REL_HWHEEL))
+        {
+            wheel_axes.insert(RelativeAxisType::REL_HWHEEL);
+            wheel_axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+        }
+        let mut wheel = output::open_uinput()?
+            .name(&wheel_name)
+            .input_id(identities.wheel.input_id(input.input_id()))
+            .with_relative_axes(&wheel_axes)?
+            .build()?;
+
+        let mut nodes = Vec::new();
+        for device in [&mut pointer, &mut wheel] {
+            nodes.extend(device.enumerate_dev_nodes_blocking()?.flatten());
+        }
+        // Grabbed before the nodes are noted as ours, so a failed grab can't
+        // leave them behind for a later device to reuse
+        input.grab()?;
+        OWN_NODES.lock().unwrap().extend(nodes.iter().cloned());
+        // Other instances only see a locked device, whatever it's called
+        let mut locks = Vec::new();
+        for node in &nodes {
+            match instance::lock(node) {
+                Ok(lock) => locks.push(lock),
+                Err(e) => info!("Can't lock {}, other instances may use it: {}", node.display(), e),
+            }
+        }
+
+        info!(
+            "Grabbed {} and re-emitting through \"{}\" and \"{}\"",
+            mouse_name, pointer_name, wheel_name
+        );
+        Ok(Passthrough {
+            pointer,
+            wheel,
+            pointer_frame: Vec::new(),
+            wheel_frame: Vec::new(),
+            nodes,
+            _locks: locks,
+        })
+    }
+
+    /// Queues an event for the current frame on whichever device it belongs
+    /// to. SYN_REPORT is added on `flush`.
+    pub fn push(&mut self, event: InputEvent) {
+        match event.kind() {
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {}
+            InputEventKind::RelAxis(axis) if WHEEL_AXES.contains(&axis) => self.wheel_frame.push(event),
+            _ => self.pointer_frame.push(event),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.pointer_frame.is_empty() {
+            self.pointer.emit(&self.pointer_frame)?;
+            self.pointer_frame.clear();
+        }
+        if !self.wheel_frame.is_empty() {
+            self.wheel.emit(&self.wheel_frame)?;
+            self.wheel_frame.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Passthrough {
+    fn drop(&mut self) {
+        OWN_NODES.lock().unwrap().retain(|node| !self.nodes.contains(node));
+    }
+}
+
+impl VirtualIdentity {
+    /// The configured name with `{name}` filled in, or `default: <mouse>`
+    /// followed by the user's name for per-user instances, like the output
+    /// device. A configured name is kept as is; the lock on the copy is what
+    /// keeps other instances off it.
+    fn name(&self, default: &str, mouse: &str) -> String {
+        match &self.name {
+            Some(template) => template.replace("{name}", mouse),
+            None => match instance::user_tag() {
+                Some(user) => format!("{}: {} ({})", default, mouse, user),
+                None => format!("{}: {}", default, mouse),
+            },
+        }
+    }
+
+    fn input_id(&self, mouse: InputId) -> InputId {
+        InputId::new(
+            mouse.bus_type(),
+            self.vendor.unwrap_or(mouse.vendor()),
+            self.product.unwrap_or(mouse.product()),
+            mouse.version(),
+        )
+    }
+}
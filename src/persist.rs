@@ -0,0 +1,57 @@
+use crate::preset::Preset;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// What was changed at runtime through the control socket, saved so a
+/// restart with `--restore-state` picks up where the daemon left off.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SavedState {
+    pub paused: bool,
+    pub preset: Option<Preset>,
+    pub device: Option<String>,
+}
+
+impl SavedState {
+    pub fn load() -> SavedState {
+        let Some(path) = state_path() else {
+            return SavedState::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+                error!("Ignoring {}: {}", path.display(), e);
+                SavedState::default()
+            }),
+            Err(_) => SavedState::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = self.try_save() {
+            error!("Can't save state: {}", e);
+        }
+    }
+
+    fn try_save(&self) -> Result<(), Box<dyn Error>> {
+        let path = state_path().ok_or("neither XDG_STATE_HOME nor HOME is set")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written aside and renamed, so a crash never leaves half a file
+        let temporary = path.with_extension("toml.tmp");
+        fs::write(&temporary, toml::to_string(self)?)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+}
+
+/// `$XDG_STATE_HOME/autoscroll/state.toml`, usually `~/.local/state/autoscroll/state.toml`.
+fn state_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("autoscroll/state.toml"))
+}
@@ -0,0 +1,189 @@
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU32, Ordering};
+use zbus::proxy;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::Connection;
+
+const DEVICE_KEYBOARD: u32 = 1;
+const DEVICE_POINTER: u32 = 2;
+const AXIS_VERTICAL: u32 = 0;
+const AXIS_HORIZONTAL: u32 = 1;
+/// Logical pixels per wheel notch, the usual Wayland step size.
+const PIXELS_PER_NOTCH: f64 = 15.0;
+
+#[proxy(
+    interface = "org.freedesktop.portal.RemoteDesktop",
+    default_service = "org.freedesktop.portal.Desktop",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait RemoteDesktop {
+    fn create_session(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<OwnedObjectPath>;
+
+    fn select_devices(
+        &self,
+        session_handle: &ObjectPath<'_>,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<OwnedObjectPath>;
+
+    fn start(
+        &self,
+        session_handle: &ObjectPath<'_>,
+        parent_window: &str,
+        options: HashMap<&str, Value<'_>>,
+    ) -> zbus::Result<OwnedObjectPath>;
+
+    fn notify_pointer_axis(
+        &self,
+        session_handle: &ObjectPath<'_>,
+        options: HashMap<&str, Value<'_>>,
+        dx: f64,
+        dy: f64,
+    ) -> zbus::Result<()>;
+
+    fn notify_keyboard_keycode(
+        &self,
+        session_handle: &ObjectPath<'_>,
+        options: HashMap<&str, Value<'_>>,
+        keycode: i32,
+        state: u32,
+    ) -> zbus::Result<()>;
+
+    fn notify_pointer_axis_discrete(
+        &self,
+        session_handle: &ObjectPath<'_>,
+        options: HashMap<&str, Value<'_>>,
+        axis: u32,
+        steps: i32,
+    ) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.portal.Request",
+    default_service = "org.freedesktop.portal.Desktop"
+)]
+trait Request {
+    #[zbus(signal)]
+    fn response(
+        &self,
+        response: u32,
+        results: HashMap<String, OwnedValue>,
+    ) -> zbus::Result<()>;
+}
+
+/// Sends wheel clicks through the RemoteDesktop portal, so no access to
+/// /dev/uinput is needed. Starting the session asks the user for permission.
+/// Only output goes this way; input is still read from /dev/input.
+pub struct RemoteDesktop {
+    proxy: RemoteDesktopProxy<'static>,
+    session: OwnedObjectPath,
+}
+
+impl RemoteDesktop {
+    pub async fn connect() -> Result<RemoteDesktop, Box<dyn Error>> {
+        let conn = Connection::session().await?;
+        let proxy = RemoteDesktopProxy::new(&conn).await?;
+
+        let session_token = token();
+        let response = request(&conn, |handle_token| {
+            let options = HashMap::from([
+                ("handle_token", Value::from(handle_token)),
+                ("session_handle_token", Value::from(session_token.as_str())),
+            ]);
+            proxy.create_session(options)
+        })
+        .await?;
+        let session = match response.get("session_handle").map(|v| &**v) {
+            Some(Value::Str(handle)) => OwnedObjectPath::try_from(handle.to_string())?,
+            Some(Value::ObjectPath(handle)) => OwnedObjectPath::from(handle.to_owned()),
+            _ => return Err("portal didn't return a session".into()),
+        };
+
+        request(&conn, |handle_token| {
+            let options = HashMap::from([
+                ("handle_token", Value::from(handle_token)),
+                // The keyboard is for apps that page with Space instead
+                ("types", Value::from(DEVICE_KEYBOARD | DEVICE_POINTER)),
+            ]);
+            proxy.select_devices(&session, options)
+        })
+        .await?;
+
+        request(&conn, |handle_token| {
+            let options = HashMap::from([("handle_token", Value::from(handle_token))]);
+            proxy.start(&session, "", options)
+        })
+        .await?;
+
+        info!("Remote desktop portal session started");
+        Ok(RemoteDesktop { proxy, session })
+    }
+
+    pub async fn scroll(&self, vertical: i32, horizontal: i32) -> zbus::Result<()> {
+        // Portal steps follow Wayland's direction, where positive scrolls down
+        if vertical != 0 {
+            self.proxy
+                .notify_pointer_axis_discrete(&self.session, HashMap::new(), AXIS_VERTICAL, -vertical)
+                .await?;
+        }
+        if horizontal != 0 {
+            self.proxy
+                .notify_pointer_axis_discrete(&self.session, HashMap::new(), AXIS_HORIZONTAL, horizontal)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Presses or releases an evdev key.
+    pub async fn key(&self, code: u16, pressed: bool) -> zbus::Result<()> {
+        self.proxy
+            .notify_keyboard_keycode(&self.session, HashMap::new(), code as i32, pressed as u32)
+            .await
+    }
+
+    /// Smooth scrolling from hi-res wheel units (120 per notch).
+    pub async fn scroll_smooth(&self, vertical: f32, horizontal: f32) -> zbus::Result<()> {
+        let to_pixels = |hi_res: f32| hi_res as f64 / 120.0 * PIXELS_PER_NOTCH;
+        self.proxy
+            .notify_pointer_axis(&self.session, HashMap::new(), to_pixels(horizontal), -to_pixels(vertical))
+            .await
+    }
+}
+
+fn token() -> String {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    format!("autoscroll{}_{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Makes a portal call and waits for its Response signal. The request path
+/// is predictable from the handle token, so we subscribe before calling and
+/// can't miss a fast reply.
+async fn request<F, Fut>(
+    conn: &Connection,
+    call: F,
+) -> Result<HashMap<String, OwnedValue>, Box<dyn Error>>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = zbus::Result<OwnedObjectPath>>,
+{
+    let handle_token = token();
+    let sender = conn
+        .unique_name()
+        .ok_or("not connected to the session bus")?
+        .trim_start_matches(':')
+        .replace('.', "_");
+    let path = format!("/org/freedesktop/portal/desktop/request/{}/{}", sender, handle_token);
+
+    let request = RequestProxy::builder(conn).path(path)?.build().await?;
+    let mut responses = request.receive_response().await?;
+    call(handle_token).await?;
+
+    let response = responses.next().await.ok_or("portal request vanished")?;
+    let args = response.args()?;
+    match args.response {
+        0 => Ok(args.results),
+        1 => Err("cancelled in the portal dialog".into()),
+        _ => Err("the portal request failed".into()),
+    }
+}
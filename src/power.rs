@@ -0,0 +1,19 @@
+use std::fs;
+
+/// Reads the power supplies in sysfs, for when UPower isn't running: on
+/// battery when there's a mains supply and it's unplugged. Desktops
+/// without a battery list no mains supply at all.
+pub fn on_battery() -> bool {
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let mains: Vec<_> = supplies
+        .flatten()
+        .map(|supply| supply.path())
+        .filter(|path| fs::read_to_string(path.join("type")).is_ok_and(|kind| kind.trim() == "Mains"))
+        .collect();
+    !mains.is_empty()
+        && !mains
+            .iter()
+            .any(|path| fs::read_to_string(path.join("online")).is_ok_and(|online| online.trim() == "1"))
+}
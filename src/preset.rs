@@ -0,0 +1,87 @@
+use crate::config::{Config, Curve};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Named bundles of deadzone, curve and speed for people who'd rather not
+/// tune four interacting numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    Slow,
+    Default,
+    Fast,
+    /// Small deadzone with speed growing quadratically, like Windows' autoscroll.
+    WindowsLike,
+}
+
+struct PresetSettings {
+    deadzone: f32,
+    base_speed: f32,
+    max_speed: i32,
+    curve: Curve,
+}
+
+impl Preset {
+    fn settings(self) -> PresetSettings {
+        match self {
+            Preset::Slow => PresetSettings {
+                deadzone: 60.0,
+                base_speed: 0.02,
+                max_speed: 3,
+                curve: Curve::Linear,
+            },
+            Preset::Default => PresetSettings {
+                deadzone: 50.0,
+                base_speed: 0.05,
+                max_speed: 5,
+                curve: Curve::Linear,
+            },
+            Preset::Fast => PresetSettings {
+                deadzone: 40.0,
+                base_speed: 0.1,
+                max_speed: 10,
+                curve: Curve::Linear,
+            },
+            Preset::WindowsLike => PresetSettings {
+                deadzone: 15.0,
+                base_speed: 0.03,
+                max_speed: 12,
+                curve: Curve::Quadratic,
+            },
+        }
+    }
+
+    pub fn apply(self, config: &mut Config) {
+        let settings = self.settings();
+        config.deadzone = settings.deadzone;
+        config.base_speed = settings.base_speed;
+        config.max_speed = settings.max_speed;
+        config.curve = settings.curve;
+    }
+
+    /// The preset as config file keys, for filling in what a file leaves out.
+    pub fn values(self) -> Vec<(String, toml::Value)> {
+        let settings = self.settings();
+        let curve = match settings.curve {
+            Curve::Linear => "linear",
+            Curve::Quadratic => "quadratic",
+            Curve::Constant => "constant",
+            Curve::Logarithmic => "logarithmic",
+        };
+        vec![
+            ("deadzone".to_string(), toml::Value::Float(settings.deadzone as f64)),
+            ("base_speed".to_string(), toml::Value::Float(settings.base_speed as f64)),
+            ("max_speed".to_string(), toml::Value::Integer(settings.max_speed as i64)),
+            ("curve".to_string(), toml::Value::String(curve.to_string())),
+        ]
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Preset::deserialize(toml::Value::String(s.to_string()))
+            .map_err(|_| format!("unknown preset: {}", s))
+    }
+}
@@ -0,0 +1,87 @@
+use crate::config::{Config, DeviceConfig, SpeedSource};
+use crate::speed::{Direction, SpeedCurve};
+
+const PLOT_WIDTH: usize = 60;
+const PLOT_HEIGHT: i32 = 16;
+/// How far to look for the distance where the curve reaches max_speed.
+const SEARCH_LIMIT: f32 = 20_000.0;
+
+/// Prints where the speed steps up as the pointer moves away from the
+/// anchor, and with `plot`, a chart of the whole curve, so a config can be
+/// judged before trying it.
+pub fn print_curve(config: &Config, device_name: Option<&str>, plot: bool) {
+    let device = device_name.map_or_else(DeviceConfig::default, |name| config.device(name));
+    let mut curve = SpeedCurve::new(config, &device);
+    // Shown for moving the pointer down, with that direction's speeds
+    let direction = if curve.invert_vertical { Direction::Up } else { Direction::Down };
+    (curve.base_speed, curve.max_speed) = curve.limits(direction);
+    let speed_at = |distance: f32| curve.scroll(0.0, distance).vertical.abs();
+
+    let mut steps = Vec::new();
+    let mut distance = 0.0;
+    let mut last = 0;
+    while distance <= SEARCH_LIMIT && last < curve.max_speed {
+        let speed = speed_at(distance);
+        if speed != last {
+            steps.push((distance, speed));
+            last = speed;
+        }
+        distance += 1.0;
+    }
+
+    let unit = match device.speed_source {
+        SpeedSource::Displacement => "counts from the anchor",
+        SpeedSource::Velocity => "counts per 100ms",
+    };
+    println!(
+        "{:?} curve scrolling {:?}, deadzone {}, base speed {}, max speed {}",
+        curve.curve, direction, curve.deadzone, curve.base_speed, curve.max_speed
+    );
+    for (distance, speed) in &steps {
+        println!("  {:>6} {}: {} per tick", distance, unit, clicks(*speed));
+    }
+    if last < curve.max_speed {
+        println!("  (max speed isn't reached within {} counts)", SEARCH_LIMIT);
+    }
+
+    if plot {
+        let end = steps.last().map_or(curve.deadzone, |(distance, _)| *distance);
+        let range = (end * 1.25).max(curve.deadzone * 2.0).max(10.0);
+        println!();
+        plot_curve(&curve, range, speed_at);
+        println!("        0{:>width$}", format!("{:.0} {}", range, unit), width = PLOT_WIDTH);
+    }
+}
+
+/// A step chart of clicks per tick against distance, with the deadzone
+/// edge marked.
+fn plot_curve(curve: &SpeedCurve, range: f32, speed_at: impl Fn(f32) -> i32) {
+    let rows = curve.max_speed.clamp(1, PLOT_HEIGHT);
+    let row_of = |speed: i32| (speed * rows + curve.max_speed - 1) / curve.max_speed.max(1);
+    let distance_of = |column: usize| column as f32 * range / PLOT_WIDTH as f32;
+    let deadzone_column = (curve.deadzone / range * PLOT_WIDTH as f32) as usize;
+
+    for row in (1..=rows).rev() {
+        let label = row * curve.max_speed / rows;
+        let line: String = (0..PLOT_WIDTH)
+            .map(|column| match row_of(speed_at(distance_of(column))) {
+                r if r == row => '*',
+                _ if column == deadzone_column => ':',
+                _ => ' ',
+            })
+            .collect();
+        println!("  {:>4} |{}", label, line.trim_end());
+    }
+    let axis: String = (0..PLOT_WIDTH)
+        .map(|column| match speed_at(distance_of(column)) {
+            0 => '*',
+            _ if column == deadzone_column => '+',
+            _ => '-',
+        })
+        .collect();
+    println!("     0 +{}", axis);
+}
+
+fn clicks(count: i32) -> String {
+    if count == 1 { "1 click".to_string() } else { format!("{} clicks", count) }
+}
@@ -0,0 +1,67 @@
+use crate::config::{PriorityConfig, RealtimePolicy};
+use std::io;
+use std::mem;
+use std::ops::RangeInclusive;
+
+/// What `SCHED_FIFO` and `SCHED_RR` accept on Linux.
+const REALTIME_PRIORITIES: RangeInclusive<i32> = 1..=99;
+
+/// Applies the `[priority]` settings to the daemon. Input reading and wheel
+/// emission share one thread, so this covers the whole scroll path.
+/// Failures are logged rather than fatal: scrolling still works, just with
+/// ordinary scheduling.
+pub fn apply(settings: &PriorityConfig) {
+    if let Some(nice) = settings.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == 0 {
+            info!("Set niceness to {}", nice);
+        } else {
+            error!("Can't set niceness to {}: {}", nice, io::Error::last_os_error());
+        }
+    }
+
+    if let Some(policy) = settings.realtime
+        && !REALTIME_PRIORITIES.contains(&settings.realtime_priority)
+    {
+        error!(
+            "Can't switch to {:?} scheduling: realtime_priority {} isn't between {} and {}",
+            policy,
+            settings.realtime_priority,
+            REALTIME_PRIORITIES.start(),
+            REALTIME_PRIORITIES.end()
+        );
+    } else if let Some(policy) = settings.realtime {
+        let param = libc::sched_param {
+            sched_priority: settings.realtime_priority,
+        };
+        let policy_id = match policy {
+            RealtimePolicy::Fifo => libc::SCHED_FIFO,
+            RealtimePolicy::Rr => libc::SCHED_RR,
+        };
+        if unsafe { libc::sched_setscheduler(0, policy_id, &param) } == 0 {
+            info!("Using {:?} scheduling at priority {}", policy, settings.realtime_priority);
+        } else {
+            error!(
+                "Can't switch to {:?} scheduling (needs CAP_SYS_NICE or an RLIMIT_RTPRIO): {}",
+                policy,
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    if let Some(cpu) = settings.cpu
+        && cpu >= libc::CPU_SETSIZE as usize
+    {
+        error!("Can't pin to CPU {}: at most {} CPUs are supported", cpu, libc::CPU_SETSIZE);
+    } else if let Some(cpu) = settings.cpu {
+        let result = unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_SET(cpu, &mut set);
+            libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+        if result == 0 {
+            info!("Pinned to CPU {}", cpu);
+        } else {
+            error!("Can't pin to CPU {}: {}", cpu, io::Error::last_os_error());
+        }
+    }
+}
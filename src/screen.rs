@@ -0,0 +1,27 @@
+use crate::xserver;
+use std::fs;
+
+/// Height of the screen in pixels: the X11 root window when there is a
+/// display, otherwise the tallest connected monitor's preferred mode as the
+/// kernel reports it, which works under Wayland and before login too.
+pub fn height() -> Option<u32> {
+    match xserver::screen_height() {
+        Ok(height) => return Some(height),
+        Err(e) => debug!("Can't ask X11 for the screen size: {}", e),
+    }
+
+    let mut tallest = None;
+    for entry in fs::read_dir("/sys/class/drm").ok()?.flatten() {
+        let path = entry.path();
+        let connected = fs::read_to_string(path.join("status")).is_ok_and(|s| s.trim() == "connected");
+        // The first mode listed is the preferred one, e.g. "3840x2160"
+        let height = fs::read_to_string(path.join("modes")).ok().and_then(|modes| {
+            let (_, height) = modes.lines().next()?.split_once('x')?;
+            height.trim_end_matches(|c: char| !c.is_ascii_digit()).parse::<u32>().ok()
+        });
+        if connected && let Some(height) = height {
+            tallest = tallest.max(Some(height));
+        }
+    }
+    tallest
+}
@@ -0,0 +1,540 @@
+use crate::config::{AppProfile, Config, Hooks, LimiterConfig, PowerSaveConfig, ScrollAs};
+use crate::diagnose;
+use crate::hooks;
+use crate::led::ScrollLockLed;
+use crate::osd::Osd;
+use crate::output::Output;
+use crate::speed::{ScrollSpeed, VELOCITY_WINDOW};
+use crate::window::WindowTracker;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time::{self, Instant};
+
+const SCROLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Autoscroll holds off for this long after the physical wheel turns.
+const WHEEL_OVERRIDE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone)]
+pub enum ScrollCommand {
+    /// The trigger was pressed on the named device.
+    Start { device: String },
+    Stop,
+    Update(ScrollSpeed),
+    /// Like `Update`, but drops back to zero unless refreshed within `VELOCITY_WINDOW`.
+    Impulse(ScrollSpeed),
+    /// Scroll right away by this many hi-res units (120 per notch), for drag-to-pan.
+    Pan { vertical: f32, horizontal: f32 },
+    /// The mouse's own wheel turned. Its events reach the desktop as usual
+    /// (through the passthrough copy when grabbed), and autoscroll pauses
+    /// meanwhile so the two don't fight over the content.
+    Wheel,
+    /// While paused, triggers are ignored and any scroll in progress stops.
+    /// Scrolls asked for on the control socket still go through.
+    Pause(bool),
+    /// Running on battery: leave out what `[power_save]` turns off.
+    PowerSave(bool),
+    /// The pointer's newest `Update` or `Impulse` is waiting in this slot.
+    /// Sent only when the slot fills, so fast motion replaces the speed
+    /// there instead of queueing speeds that are stale by the time they're
+    /// read.
+    Latest(SpeedSlot),
+}
+
+/// Holds a pointer's newest speed until the scroll task takes it.
+pub type SpeedSlot = Arc<Mutex<Option<ScrollCommand>>>;
+
+/// Tells one seat's commands apart by the pointer that sent them.
+pub type PointerId = u32;
+/// Commands from the control socket rather than from a reader.
+pub const CONTROL_POINTER: PointerId = 0;
+
+/// A reader's handle on its seat's scroll task. Every reader gets its own
+/// id, so two pointers on one seat (a second mouse, or an X11 MPX pointer)
+/// each have their own scroll that the other can't stop. Dropping it stops
+/// the pointer's scroll, so a reader that ends mid-scroll can't leave it
+/// running.
+#[derive(Debug)]
+pub struct PointerSender {
+    pointer: PointerId,
+    tx: UnboundedSender<(PointerId, ScrollCommand)>,
+    latest: SpeedSlot,
+}
+
+impl PointerSender {
+    pub fn new(pointer: PointerId, tx: UnboundedSender<(PointerId, ScrollCommand)>) -> Self {
+        PointerSender { pointer, tx, latest: SpeedSlot::default() }
+    }
+
+    /// Speeds go through the pointer's slot, everything else in order. A
+    /// speed still waiting when an ordered command goes out is left in its
+    /// slot, whose `Latest` is already queued ahead of the command, and
+    /// later speeds get a new slot. So a speed can't be taken after a
+    /// `Stop` and land in the following scroll, nor one from the following
+    /// scroll be taken before the `Stop`.
+    pub fn send(&mut self, command: ScrollCommand) -> Result<(), SendError<ScrollCommand>> {
+        match command {
+            ScrollCommand::Update(_) | ScrollCommand::Impulse(_) => {
+                if self.latest.lock().unwrap().replace(command).is_some() {
+                    return Ok(());
+                }
+                self.send_ordered(ScrollCommand::Latest(self.latest.clone()))
+            }
+            command => {
+                if self.latest.lock().unwrap().is_some() {
+                    self.latest = SpeedSlot::default();
+                }
+                self.send_ordered(command)
+            }
+        }
+    }
+
+    fn send_ordered(&self, command: ScrollCommand) -> Result<(), SendError<ScrollCommand>> {
+        self.tx
+            .send((self.pointer, command))
+            .map_err(|SendError((_, command))| SendError(command))
+    }
+}
+
+impl Drop for PointerSender {
+    fn drop(&mut self) {
+        let _ = self.tx.send((self.pointer, ScrollCommand::Stop));
+    }
+}
+
+/// What one pointer is asking for while its trigger is held.
+#[derive(Debug)]
+struct PointerState {
+    started: Instant,
+    speed: ScrollSpeed,
+    impulse_expires: Option<Instant>,
+    /// When the speed last went up from zero, for the ramp.
+    moving_since: Option<Instant>,
+}
+
+impl PointerState {
+    fn new(now: Instant) -> Self {
+        PointerState {
+            started: now,
+            speed: ScrollSpeed::default(),
+            impulse_expires: None,
+            moving_since: None,
+        }
+    }
+
+    fn set_speed(&mut self, speed: ScrollSpeed, now: Instant) {
+        if speed.is_zero() {
+            self.moving_since = None;
+        } else if self.moving_since.is_none() {
+            self.moving_since = Some(now);
+        }
+        self.speed = speed;
+    }
+
+    /// The speed, held back while it's still ramping up.
+    fn ramped(&self, ramp: Duration, now: Instant) -> ScrollSpeed {
+        match self.moving_since {
+            Some(since) if !ramp.is_zero() => {
+                self.speed.scale((now - since).as_secs_f32() / ramp.as_secs_f32())
+            }
+            _ => self.speed,
+        }
+    }
+}
+
+/// The parts of the config a scroll task works from.
+pub struct Settings {
+    pub apps: Vec<AppProfile>,
+    pub hooks: Hooks,
+    pub power: PowerSaveConfig,
+    pub ramp: Duration,
+    pub limiter: LimiterConfig,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        Settings {
+            apps: config.apps.clone(),
+            hooks: config.hooks(),
+            power: config.power_save.clone(),
+            ramp: Duration::from_millis(config.ramp_ms),
+            limiter: config.limiter.clone(),
+        }
+    }
+}
+
+/// Turns commands from the input readers into wheel events on one output.
+/// Sleeps until the next tick is due instead of polling, so an idle daemon
+/// doesn't wake up at all. While several pointers scroll at once, their
+/// speeds add up.
+pub async fn scroll_task(
+    mut output: Output,
+    mut rx: UnboundedReceiver<(PointerId, ScrollCommand)>,
+    settings: Settings,
+    mut osd: Option<Osd>,
+    mut led: Option<ScrollLockLed>,
+) {
+    let Settings { apps, hooks, power, ramp, limiter } = settings;
+    let max_scroll = (limiter.max_scroll_secs > 0).then(|| Duration::from_secs(limiter.max_scroll_secs));
+    let mut budget = Budget::new(limiter.max_clicks_per_second);
+    let mut last_scroll = Instant::now();
+    // The speed the last tick went out at; 0 until the first one
+    let mut tick_speed = 0;
+    // Only pointers with their trigger held
+    let mut pointers: HashMap<PointerId, PointerState> = HashMap::new();
+    let mut windows = WindowTracker::default();
+    // The device that last started a scroll, to notice switching mice
+    let mut last_device: Option<String> = None;
+    let mut profile: Option<&AppProfile> = None;
+    // Ticks held back by an app's min_interval_ms, sent together later
+    let mut pending = ScrollSpeed::default();
+    // Clicks towards the next page for apps that page with Space
+    let mut page_clicks = 0;
+    let mut last_emit = Instant::now();
+    // While the physical wheel is in charge
+    let mut wheel_until: Option<Instant> = None;
+    let mut paused = false;
+    let mut power_save = false;
+    // The OSD and LED while power saving has them turned off
+    let (mut parked_osd, mut parked_led) = (None, None);
+
+    loop {
+        let scroll_value = total_speed(&pointers, ramp, Instant::now());
+        let next_tick = (!scroll_value.is_zero()).then(|| {
+            tick_due(last_scroll, tick_speed, scroll_value).max(wheel_until.unwrap_or(last_scroll))
+        });
+        let impulses = pointers.values().filter_map(|p| p.impulse_expires);
+        let overdue = pointers.values().filter_map(|p| max_scroll.map(|max| p.started + max));
+        let wake_at = next_tick.into_iter().chain(impulses).chain(overdue).min();
+
+        tokio::select! {
+            command = next_command(&mut rx) => match command {
+                Some((_, ScrollCommand::Start { device })) if paused => {
+                    status!("{}: trigger ignored while autoscroll is paused", device);
+                }
+                Some((pointer, ScrollCommand::Pan { .. })) if paused && pointer != CONTROL_POINTER => {}
+                Some((_, ScrollCommand::Pause(pause))) => {
+                    paused = pause;
+                    if pause && !pointers.is_empty() {
+                        pointers.clear();
+                        stop_effects(&hooks, last_device.as_deref(), &mut osd, &mut led);
+                        pending = ScrollSpeed::default();
+                    }
+                }
+                Some((_, ScrollCommand::PowerSave(on))) => {
+                    power_save = on;
+                    if on {
+                        if !power.osd && let Some(mut osd) = osd.take() {
+                            osd.hide();
+                            parked_osd = Some(osd);
+                        }
+                        if !power.scroll_lock_led && let Some(mut led) = led.take() {
+                            led.off();
+                            parked_led = Some(led);
+                        }
+                    } else {
+                        osd = osd.or(parked_osd.take());
+                        led = led.or(parked_led.take());
+                    }
+                }
+                Some((pointer, ScrollCommand::Start { device })) => {
+                    let scrolling = !pointers.is_empty();
+                    if last_device.as_ref() != Some(&device) {
+                        info!("Now scrolling with {}", device);
+                        if let Some(command) = &hooks.on_device_change {
+                            hooks::run("on_device_change", command, &device);
+                        }
+                        last_device = Some(device.clone());
+                    }
+                    if !scrolling && let Some(command) = &hooks.on_scroll_start {
+                        hooks::run("on_scroll_start", command, &device);
+                    }
+                    if !scrolling && let Some(osd) = &mut osd {
+                        osd.show();
+                    }
+                    if !scrolling && let Some(led) = &mut led {
+                        led.on();
+                    }
+                    if !scrolling {
+                        last_scroll = Instant::now();
+                        tick_speed = 0;
+                        page_clicks = 0;
+                    }
+                    pointers.entry(pointer).or_insert_with(|| PointerState::new(Instant::now()));
+                    if !apps.is_empty() {
+                        let window = windows.active_class();
+                        profile = window.and_then(|w| apps.iter().find(|app| app.matches(&w)));
+                        if let Some(app) = profile {
+                            debug!("Using app profile for {}", app.class);
+                            if app.max_speed.is_some_and(|max| max <= 0) {
+                                status!("The app profile for {} has max_speed = 0, so nothing scrolls", app.class);
+                            }
+                        }
+                    }
+                }
+                Some((pointer, ScrollCommand::Stop)) => {
+                    if pointers.remove(&pointer).is_some() && pointers.is_empty() {
+                        stop_effects(&hooks, last_device.as_deref(), &mut osd, &mut led);
+                        pending = ScrollSpeed::default();
+                    }
+                }
+                Some((pointer, ScrollCommand::Update(new_value))) => {
+                    if let Some(state) = pointers.get_mut(&pointer) {
+                        state.set_speed(new_value, Instant::now());
+                        state.impulse_expires = None;
+                    }
+                }
+                Some((_, ScrollCommand::Pan { vertical, horizontal })) => {
+                    if let Err(e) = output.pan(vertical, horizontal).await {
+                        error!("Failed to send scroll event: {}", e);
+                        status!("Output failed, this seat stops scrolling: {}", e);
+                        break;
+                    }
+                }
+                Some((_, ScrollCommand::Wheel)) => {
+                    if !pointers.is_empty() {
+                        wheel_until = Some(Instant::now() + WHEEL_OVERRIDE);
+                        pending = ScrollSpeed::default();
+                    }
+                }
+                Some((pointer, ScrollCommand::Impulse(new_value))) => {
+                    if let Some(state) = pointers.get_mut(&pointer) {
+                        state.set_speed(new_value, Instant::now());
+                        state.impulse_expires = Some(Instant::now() + VELOCITY_WINDOW);
+                    }
+                }
+                Some((_, ScrollCommand::Latest(_))) => unreachable!("next_command takes speeds out of their slot"),
+                // Every reader for this seat is gone
+                None => break,
+            },
+            _ = sleep_until(wake_at) => {}
+        }
+        let now = Instant::now();
+        let lateness = wake_at.filter(|due| now >= *due).map(|due| now - due);
+        diagnose::scroll_wakeup(lateness, rx.len());
+
+        for state in pointers.values_mut() {
+            if state.impulse_expires.is_some_and(|expires| now >= expires) {
+                state.set_speed(ScrollSpeed::default(), now);
+                state.impulse_expires = None;
+            }
+        }
+        if let Some(max) = max_scroll {
+            let stuck = pointers.len();
+            pointers.retain(|_, state| now < state.started + max);
+            if pointers.len() < stuck {
+                error!("A scroll went on for over {}s, stopping it", max.as_secs());
+                status!("Stopped a scroll held for over {}s (max_scroll_secs)", max.as_secs());
+                if pointers.is_empty() {
+                    stop_effects(&hooks, last_device.as_deref(), &mut osd, &mut led);
+                    pending = ScrollSpeed::default();
+                }
+            }
+        }
+        if wheel_until.is_some_and(|until| now >= until) {
+            wheel_until = None;
+        }
+        let scrolling = !pointers.is_empty();
+        let scroll_value = total_speed(&pointers, ramp, now);
+
+        if scrolling && let Some(osd) = &mut osd {
+            osd.update(scroll_value);
+        }
+
+        // Perform scrolling if active
+        if scrolling
+            && wheel_until.is_none()
+            && !scroll_value.is_zero()
+            && now >= tick_due(last_scroll, tick_speed, scroll_value)
+        {
+            pending += profile.map_or(scroll_value, |app| app.limit(scroll_value));
+            last_scroll = now;
+            tick_speed = scroll_value.magnitude();
+
+            let mut min_interval = profile.map_or(Duration::ZERO, |app| app.min_interval());
+            if power_save {
+                min_interval = min_interval.max(power.min_interval());
+            }
+            if last_emit.elapsed() >= min_interval {
+                let scroll_value = budget.take(std::mem::take(&mut pending), now);
+                last_emit = Instant::now();
+
+                let sent = match profile.map(|app| (app.scroll_as, app)) {
+                    Some((ScrollAs::Space, app)) => {
+                        page_clicks += scroll_value.vertical;
+                        let pages = page_clicks / app.clicks_per_page();
+                        page_clicks %= app.clicks_per_page();
+                        if pages != 0 { output.page(pages).await } else { Ok(()) }
+                    }
+                    _ if !scroll_value.is_zero() => output.send(scroll_value).await,
+                    _ => Ok(()),
+                };
+                if let Err(e) = sent {
+                    error!("Failed to send scroll event: {}", e);
+                    status!("Output failed, this seat stops scrolling: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The next command, taking a waiting speed out of its slot as late as
+/// possible so it's the freshest one. An empty slot is skipped.
+async fn next_command(rx: &mut UnboundedReceiver<(PointerId, ScrollCommand)>) -> Option<(PointerId, ScrollCommand)> {
+    loop {
+        match rx.recv().await? {
+            (pointer, ScrollCommand::Latest(slot)) => {
+                if let Some(command) = slot.lock().unwrap().take() {
+                    return Some((pointer, command));
+                }
+            }
+            command => return Some(command),
+        }
+    }
+}
+
+/// Caps the wheel clicks sent within each second.
+struct Budget {
+    per_second: i32,
+    window_start: Instant,
+    used: i32,
+}
+
+impl Budget {
+    fn new(per_second: i32) -> Self {
+        Budget { per_second, window_start: Instant::now(), used: 0 }
+    }
+
+    /// As much of `speed` as this second still allows.
+    fn take(&mut self, speed: ScrollSpeed, now: Instant) -> ScrollSpeed {
+        if self.per_second <= 0 {
+            return speed;
+        }
+        if now - self.window_start >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.used = 0;
+        }
+        let left = self.per_second - self.used;
+        let allowed = ScrollSpeed {
+            vertical: speed.vertical.clamp(-left, left),
+            horizontal: speed.horizontal.clamp(-left, left),
+        };
+        if allowed != speed && self.used < self.per_second {
+            debug!("Holding scrolling to {} clicks a second", self.per_second);
+            status!("Holding scrolling to {} clicks a second (max_clicks_per_second)", self.per_second);
+        }
+        self.used += allowed.magnitude();
+        allowed
+    }
+}
+
+/// Hooks, OSD and LED for the last pointer letting go.
+fn stop_effects(hooks: &Hooks, device: Option<&str>, osd: &mut Option<Osd>, led: &mut Option<ScrollLockLed>) {
+    if let Some(command) = &hooks.on_scroll_stop {
+        hooks::run("on_scroll_stop", command, device.unwrap_or(""));
+    }
+    if let Some(osd) = osd {
+        osd.hide();
+    }
+    if let Some(led) = led {
+        led.off();
+    }
+}
+
+/// When the next tick is due. Speeding up brings it forward in proportion,
+/// so leaving the deadzone scrolls at once and a faster speed takes effect
+/// without waiting out a whole interval at the old one.
+fn tick_due(last_scroll: Instant, tick_speed: i32, speed: ScrollSpeed) -> Instant {
+    let share = tick_speed as f32 / speed.magnitude().max(1) as f32;
+    last_scroll + SCROLL_INTERVAL.mul_f32(share.min(1.0))
+}
+
+fn total_speed(pointers: &HashMap<PointerId, PointerState>, ramp: Duration, now: Instant) -> ScrollSpeed {
+    let mut total = ScrollSpeed::default();
+    for state in pointers.values() {
+        total += state.ramped(ramp, now);
+    }
+    total
+}
+
+pub async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    #[test]
+    fn budget_caps_each_second() {
+        let mut budget = Budget::new(10);
+        let start = budget.window_start;
+        let fast = ScrollSpeed { vertical: -6, horizontal: 0 };
+        assert_eq!(budget.take(fast, start), fast);
+        assert_eq!(budget.take(fast, start), ScrollSpeed { vertical: -4, horizontal: 0 });
+        assert!(budget.take(fast, start).is_zero());
+        assert_eq!(budget.take(fast, start + Duration::from_secs(1)), fast);
+    }
+
+    fn speed(vertical: i32) -> ScrollCommand {
+        ScrollCommand::Update(ScrollSpeed { vertical, horizontal: 0 })
+    }
+
+    /// The vertical speed of the next command, or `None` for a `Stop`.
+    async fn next_speed(rx: &mut UnboundedReceiver<(PointerId, ScrollCommand)>) -> Option<i32> {
+        match next_command(rx).await {
+            Some((1, ScrollCommand::Update(speed))) => Some(speed.vertical),
+            Some((1, ScrollCommand::Stop)) => None,
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_latest_speed_arrives() {
+        let (tx, mut rx) = unbounded_channel();
+        let mut sender = PointerSender::new(1, tx);
+        for vertical in 1..=5 {
+            sender.send(speed(vertical)).unwrap();
+        }
+        assert_eq!(next_speed(&mut rx).await, Some(5));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn speeds_stay_ahead_of_a_stop() {
+        let (tx, mut rx) = unbounded_channel();
+        let mut sender = PointerSender::new(1, tx);
+        sender.send(speed(2)).unwrap();
+        sender.send(ScrollCommand::Stop).unwrap();
+        sender.send(speed(3)).unwrap();
+        assert_eq!(next_speed(&mut rx).await, Some(2));
+        assert_eq!(next_speed(&mut rx).await, None);
+        assert_eq!(next_speed(&mut rx).await, Some(3));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_sender_stops() {
+        let (tx, mut rx) = unbounded_channel();
+        let mut sender = PointerSender::new(1, tx);
+        sender.send(speed(4)).unwrap();
+        drop(sender);
+        assert_eq!(next_speed(&mut rx).await, Some(4));
+        assert_eq!(next_speed(&mut rx).await, None);
+        assert!(next_command(&mut rx).await.is_none());
+    }
+
+    #[test]
+    fn budget_off() {
+        let mut budget = Budget::new(0);
+        let fast = ScrollSpeed { vertical: 500, horizontal: 500 };
+        assert_eq!(budget.take(fast, Instant::now()), fast);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::config::Config;
+use crate::discovery::{find_input_devices, seat_of};
+use crate::instance;
+use crate::output::Output;
+use crate::speed::ScrollSpeed;
+use evdev::Key;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+/// How long the desktop gets to pick up a freshly created virtual device.
+const SETTLE: Duration = Duration::from_millis(500);
+/// How long to wait for the test scrolls to reach the window.
+#[cfg(feature = "x11")]
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+enum Outcome {
+    Pass(String),
+    Fail(String),
+    Skip(String),
+}
+
+/// Goes through what scrolling needs, one step after another, for
+/// `autoscroll self-test`: a mouse that can be opened, an output device
+/// that can be created and written to, and on X11 the scrolling actually
+/// arriving in a window. Fails when any step does.
+pub async fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut results = Vec::new();
+
+    let mouse = find_input_devices(config).ok().and_then(|devices| devices.mice.into_iter().next());
+    results.push(("mouse", open_mouse(config, mouse.as_deref())));
+
+    let seat = mouse.as_deref().map_or_else(|| "seat0".to_string(), seat_of);
+    match Output::new(config, &seat).await {
+        Ok(mut output) => {
+            results.push(("output", Outcome::Pass(format!("{:?} backend for {}", config.output, seat))));
+            tokio::time::sleep(SETTLE).await;
+            results.push(("emit", emit(&mut output).await));
+            results.push(("receive", receive(&mut output).await));
+        }
+        Err(e) => {
+            results.push(("output", Outcome::Fail(e.to_string())));
+            results.push(("emit", Outcome::Skip("no output device".to_string())));
+            results.push(("receive", Outcome::Skip("no output device".to_string())));
+        }
+    }
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for (step, outcome) in &results {
+        let (label, detail) = match outcome {
+            Outcome::Pass(detail) => {
+                passed += 1;
+                ("PASS", detail)
+            }
+            Outcome::Fail(detail) => {
+                failed += 1;
+                ("FAIL", detail)
+            }
+            Outcome::Skip(detail) => {
+                skipped += 1;
+                ("SKIP", detail)
+            }
+        };
+        println!("{}  {:<8} {}", label, step, detail);
+    }
+    println!("\n{} passed, {} failed, {} skipped", passed, failed, skipped);
+
+    if failed > 0 {
+        return Err(format!("{} of {} checks failed", failed, results.len()).into());
+    }
+    Ok(())
+}
+
+/// Opens the mouse the daemon would use and checks it has the trigger.
+fn open_mouse(config: &Config, path: Option<&str>) -> Outcome {
+    let Some(path) = path else {
+        return Outcome::Fail("no mouse found, see --list-devices".to_string());
+    };
+    let device = match instance::claim(path) {
+        Ok(Some(device)) => device,
+        Ok(None) => return Outcome::Pass(format!("{} is in use by a running autoscroll", path)),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return Outcome::Fail(format!("can't open {}: {} (is the user in the input group?)", path, e));
+        }
+        Err(e) => return Outcome::Fail(format!("can't open {}: {}", path, e)),
+    };
+    let name = device.name().unwrap_or("Unknown");
+    let trigger = config.device(name).trigger.unwrap_or(Key::BTN_MIDDLE);
+    if device.supported_keys().is_some_and(|keys| keys.contains(trigger)) {
+        Outcome::Pass(format!("{} ({}), trigger {:?}", path, name, trigger))
+    } else {
+        Outcome::Fail(format!("{} ({}) has no {:?} to trigger with", path, name, trigger))
+    }
+}
+
+/// One click up and one back down, so nothing ends up scrolled.
+async fn emit(output: &mut Output) -> Outcome {
+    for vertical in [1, -1] {
+        if let Err(e) = output.send(ScrollSpeed { vertical, horizontal: 0 }).await {
+            return Outcome::Fail(format!("sending a scroll event: {}", e));
+        }
+    }
+    Outcome::Pass("sent a click up and a click down".to_string())
+}
+
+/// Scrolls again while a test window holds the pointer, and checks both
+/// clicks arrive as X11 wheel buttons.
+#[cfg(feature = "x11")]
+async fn receive(output: &mut Output) -> Outcome {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return Outcome::Skip("Wayland session, X11 doesn't see every scroll".to_string());
+    }
+    if std::env::var_os("DISPLAY").is_none() {
+        return Outcome::Skip("no X11 display".to_string());
+    }
+    let window = match x11::TestWindow::grab() {
+        Ok(window) => window,
+        Err(e) => return Outcome::Skip(format!("no test window: {}", e)),
+    };
+    if let Outcome::Fail(e) = emit(output).await {
+        return Outcome::Fail(e);
+    }
+
+    let deadline = tokio::time::Instant::now() + RECEIVE_TIMEOUT;
+    let (mut up, mut down) = (false, false);
+    while !(up && down) && tokio::time::Instant::now() < deadline {
+        match window.buttons() {
+            Ok(buttons) => {
+                up |= buttons.contains(&x11::WHEEL_UP);
+                down |= buttons.contains(&x11::WHEEL_DOWN);
+            }
+            Err(e) => return Outcome::Fail(format!("reading X11 events: {}", e)),
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    match (up, down) {
+        (true, true) => Outcome::Pass("the test window got both clicks".to_string()),
+        (false, false) => Outcome::Fail(format!(
+            "nothing reached the test window within {}ms; is the virtual device ignored by the desktop?",
+            RECEIVE_TIMEOUT.as_millis()
+        )),
+        _ => Outcome::Fail("the test window got only one of the two clicks".to_string()),
+    }
+}
+
+#[cfg(not(feature = "x11"))]
+async fn receive(_output: &mut Output) -> Outcome {
+    Outcome::Skip("this build has no X11 support".to_string())
+}
+
+#[cfg(feature = "x11")]
+mod x11 {
+    use std::error::Error;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        ConnectionExt, CreateWindowAux, EventMask, GrabMode, GrabStatus, Window, WindowClass,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+    /// X11 reports the wheel as buttons 4 (up) and 5 (down).
+    pub const WHEEL_UP: u8 = 4;
+    pub const WHEEL_DOWN: u8 = 5;
+
+    /// A small window under the pointer with the pointer grabbed, so every
+    /// pointer event goes to it whatever the pointer is over.
+    pub struct TestWindow {
+        conn: RustConnection,
+        window: Window,
+    }
+
+    impl TestWindow {
+        pub fn grab() -> Result<TestWindow, Box<dyn Error>> {
+            let (conn, screen) = x11rb::connect(None)?;
+            let root = conn.setup().roots[screen].root;
+            let pointer = conn.query_pointer(root)?.reply()?;
+
+            let window = conn.generate_id()?;
+            conn.create_window(
+                COPY_DEPTH_FROM_PARENT,
+                window,
+                root,
+                pointer.root_x,
+                pointer.root_y,
+                1,
+                1,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                0,
+                &CreateWindowAux::new().override_redirect(1).event_mask(EventMask::BUTTON_PRESS),
+            )?;
+            conn.map_window(window)?;
+            conn.sync()?;
+
+            let mask = EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE;
+            let status = conn
+                .grab_pointer(false, window, mask, GrabMode::ASYNC, GrabMode::ASYNC, NONE, NONE, CURRENT_TIME)?
+                .reply()?
+                .status;
+            let test = TestWindow { conn, window };
+            if status != GrabStatus::SUCCESS {
+                return Err(format!("pointer grab refused ({:?})", status).into());
+            }
+            Ok(test)
+        }
+
+        /// Buttons pressed since the last call.
+        pub fn buttons(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+            let mut buttons = Vec::new();
+            while let Some(event) = self.conn.poll_for_event()? {
+                if let Event::ButtonPress(press) = event {
+                    buttons.push(press.detail);
+                }
+            }
+            Ok(buttons)
+        }
+    }
+
+    impl Drop for TestWindow {
+        fn drop(&mut self) {
+            let _ = self.conn.ungrab_pointer(CURRENT_TIME);
+            let _ = self.conn.destroy_window(self.window);
+            let _ = self.conn.flush();
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::config::SessionPause;
+use futures_util::stream::StreamExt;
+use std::error::Error;
+use zbus::proxy::PropertyStream;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{proxy, Connection};
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session_by_pid(&self, pid: u32) -> zbus::Result<OwnedObjectPath>;
+    fn get_seat(&self, seat_id: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(interface = "org.freedesktop.login1.Session", default_service = "org.freedesktop.login1")]
+trait Session {
+    #[zbus(property)]
+    fn active(&self) -> zbus::Result<bool>;
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+    #[zbus(property)]
+    fn class(&self) -> zbus::Result<String>;
+}
+
+#[proxy(interface = "org.freedesktop.login1.Seat", default_service = "org.freedesktop.login1")]
+trait Seat {
+    #[zbus(property)]
+    fn active_session(&self) -> zbus::Result<(String, OwnedObjectPath)>;
+}
+
+/// Follows a logind session, to stop scrolling into another VT or a lock
+/// screen. That's the daemon's own session, or when it runs as a system
+/// service outside any session, whichever one is in front on seat0.
+pub struct SessionWatch {
+    conn: Connection,
+    pause: SessionPause,
+    session: SessionProxy<'static>,
+    active: PropertyStream<'static, bool>,
+    locked: PropertyStream<'static, bool>,
+    /// Only when following seat0 rather than a session of our own.
+    seat: Option<PropertyStream<'static, (String, OwnedObjectPath)>>,
+    paused: bool,
+}
+
+impl SessionWatch {
+    pub async fn connect(pause: SessionPause) -> Result<SessionWatch, Box<dyn Error>> {
+        let conn = Connection::system().await?;
+        let manager = ManagerProxy::new(&conn).await?;
+        let (path, seat) = match manager.get_session_by_pid(std::process::id()).await {
+            Ok(path) => (path, None),
+            Err(_) => {
+                let seat = SeatProxy::builder(&conn).path(manager.get_seat("seat0").await?)?.build().await?;
+                let (_, path) = seat.active_session().await?;
+                (path, Some(seat.receive_active_session_changed().await))
+            }
+        };
+        info!("Following logind session {}", path.as_str());
+
+        let session = session(&conn, path).await?;
+        let mut watch = SessionWatch {
+            active: session.receive_active_changed().await,
+            locked: session.receive_locked_hint_changed().await,
+            conn,
+            pause,
+            session,
+            seat,
+            paused: false,
+        };
+        watch.paused = watch.should_pause().await;
+        Ok(watch)
+    }
+
+    /// Whether scrolling should be paused right now.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Waits until scrolling should be paused or resumed, and says which.
+    pub async fn changed(&mut self) -> Result<bool, Box<dyn Error>> {
+        loop {
+            tokio::select! {
+                Some(_) = self.active.next() => {}
+                Some(_) = self.locked.next() => {}
+                Some(change) = next_session(&mut self.seat) => {
+                    let (_, path) = change.get().await?;
+                    debug!("Now following logind session {}", path.as_str());
+                    self.session = session(&self.conn, path).await?;
+                    self.active = self.session.receive_active_changed().await;
+                    self.locked = self.session.receive_locked_hint_changed().await;
+                }
+                else => return Err("logind went away".into()),
+            }
+            let paused = self.should_pause().await;
+            if paused != self.paused {
+                self.paused = paused;
+                return Ok(paused);
+            }
+        }
+    }
+
+    async fn should_pause(&self) -> bool {
+        // Treat properties that can't be read as the harmless case
+        let active = self.session.active().await.unwrap_or(true);
+        let locked = self.session.locked_hint().await.unwrap_or(false);
+        let greeter = self.session.class().await.is_ok_and(|class| class == "greeter");
+        match self.pause {
+            SessionPause::Never => false,
+            SessionPause::Inactive => !active || greeter,
+            SessionPause::InactiveOrLocked => !active || greeter || locked,
+        }
+    }
+}
+
+async fn session(conn: &Connection, path: OwnedObjectPath) -> zbus::Result<SessionProxy<'static>> {
+    SessionProxy::builder(conn).path(path)?.build().await
+}
+
+async fn next_session(
+    seat: &mut Option<PropertyStream<'static, (String, OwnedObjectPath)>>,
+) -> Option<zbus::proxy::PropertyChanged<'static, (String, OwnedObjectPath)>> {
+    match seat {
+        Some(seat) => seat.next().await,
+        None => std::future::pending().await,
+    }
+}
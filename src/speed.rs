@@ -0,0 +1,394 @@
+use crate::config::{Config, Curve, DeadzoneShape, DeviceConfig, DeviceMode, DirectionSpeeds};
+use std::ops::AddAssign;
+use std::time::{Duration, SystemTime};
+
+/// How long a velocity-based speed stays valid without new motion.
+pub const VELOCITY_WINDOW: Duration = Duration::from_millis(100);
+
+/// Wheel clicks to send on each scroll tick, using the wheel's own signs:
+/// positive vertical scrolls up, positive horizontal scrolls right.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScrollSpeed {
+    pub vertical: i32,
+    pub horizontal: i32,
+}
+
+impl ScrollSpeed {
+    pub fn is_zero(&self) -> bool {
+        self.vertical == 0 && self.horizontal == 0
+    }
+
+    /// Scaled down by `factor` (up to 1), still at least a click on any axis that scrolls.
+    pub fn scale(self, factor: f32) -> ScrollSpeed {
+        let scale = |clicks: i32| {
+            let scaled = (clicks as f32 * factor.min(1.0)).round() as i32;
+            if clicks != 0 && scaled == 0 { clicks.signum() } else { scaled }
+        };
+        ScrollSpeed { vertical: scale(self.vertical), horizontal: scale(self.horizontal) }
+    }
+
+    /// `tiers` clicks per tick faster (or slower, if negative) on each axis
+    /// that scrolls, never dropping below one click.
+    pub fn nudge(self, tiers: i32) -> ScrollSpeed {
+        let nudge = |clicks: i32| {
+            if clicks == 0 { 0 } else { clicks.signum() * (clicks.abs() + tiers).max(1) }
+        };
+        ScrollSpeed { vertical: nudge(self.vertical), horizontal: nudge(self.horizontal) }
+    }
+
+    /// Clicks per tick along the faster axis.
+    pub fn magnitude(&self) -> i32 {
+        self.vertical.abs().max(self.horizontal.abs())
+    }
+}
+
+impl AddAssign for ScrollSpeed {
+    fn add_assign(&mut self, other: ScrollSpeed) {
+        self.vertical += other.vertical;
+        self.horizontal += other.horizontal;
+    }
+}
+
+/// Which way the content scrolls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Maps pointer displacement (in device counts) to wheel clicks per scroll
+/// tick. Moving the pointer down scrolls down unless inverted.
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedCurve {
+    pub deadzone: f32,
+    pub deadzone_horizontal: f32,
+    pub deadzone_shape: DeadzoneShape,
+    pub base_speed: f32,
+    pub max_speed: i32,
+    pub curve: Curve,
+    pub horizontal: bool,
+    pub invert_vertical: bool,
+    pub invert_horizontal: bool,
+    pub directions: DirectionSpeeds,
+    /// Hi-res wheel units per count of motion when dragging to pan instead
+    /// of autoscrolling.
+    pub pan_speed: Option<f32>,
+}
+
+impl SpeedCurve {
+    pub fn new(config: &Config, device: &DeviceConfig) -> Self {
+        let deadzone = device.deadzone.unwrap_or(config.deadzone);
+        SpeedCurve {
+            deadzone,
+            deadzone_horizontal: config.deadzone_horizontal.unwrap_or(deadzone),
+            deadzone_shape: config.deadzone_shape,
+            base_speed: device.base_speed.unwrap_or(config.base_speed),
+            max_speed: device.max_speed.unwrap_or(config.max_speed),
+            curve: match device.curve {
+                Some(curve) => curve,
+                None if device.mode == DeviceMode::Trackball => Curve::Logarithmic,
+                None => config.curve,
+            },
+            horizontal: config.horizontal,
+            invert_vertical: config.invert_vertical,
+            invert_horizontal: config.invert_horizontal,
+            directions: config.direction,
+            pan_speed: config.pan.then_some(config.pan_speed),
+        }
+    }
+
+    /// Changes the deadzone, keeping the sideways size in proportion.
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.deadzone_horizontal *= deadzone / self.deadzone.max(f32::EPSILON);
+        self.deadzone = deadzone;
+    }
+
+    pub fn scroll(&self, dx: f32, dy: f32) -> ScrollSpeed {
+        // Sideways motion doesn't count towards leaving the deadzone unless
+        // it scrolls too.
+        let dx = if self.horizontal { dx } else { 0.0 };
+        // Moving down scrolls down and right scrolls right, unless inverted
+        let vertical_direction = if (dy > 0.0) != self.invert_vertical { Direction::Down } else { Direction::Up };
+        let horizontal_direction =
+            if (dx > 0.0) != self.invert_horizontal { Direction::Right } else { Direction::Left };
+        let directions = (horizontal_direction, vertical_direction);
+        let (horizontal, vertical) = match self.deadzone_shape {
+            DeadzoneShape::Square => (
+                self.speed(past(dx, self.deadzone_horizontal), horizontal_direction),
+                self.speed(past(dy, self.deadzone), vertical_direction),
+            ),
+            DeadzoneShape::Circle => self.radial(dx, dy, self.deadzone, self.deadzone, directions),
+            DeadzoneShape::Ellipse => {
+                self.radial(dx, dy, self.deadzone_horizontal, self.deadzone, directions)
+            }
+        };
+        let vertical = -vertical;
+
+        ScrollSpeed {
+            vertical: if self.invert_vertical { -vertical } else { vertical },
+            horizontal: if self.invert_horizontal { -horizontal } else { horizontal },
+        }
+    }
+
+    /// Smooth scrolling for one frame of motion when panning, as (vertical,
+    /// horizontal) hi-res units. The content follows the hand: dragging
+    /// down scrolls up. Panning always works on both axes.
+    pub fn pan(&self, dx: f32, dy: f32) -> Option<(f32, f32)> {
+        let speed = self.pan_speed?;
+        let vertical = dy * speed;
+        let horizontal = -dx * speed;
+        Some((
+            if self.invert_vertical { -vertical } else { vertical },
+            if self.invert_horizontal { -horizontal } else { horizontal },
+        ))
+    }
+
+    /// Clicks per tick along one axis, for a distance already past the
+    /// deadzone edge, with the same sign as the distance.
+    pub fn speed(&self, excess: f32, direction: Direction) -> i32 {
+        let speed = self.magnitude(excess.abs(), direction) as i32;
+        if excess < 0.0 { -speed } else { speed }
+    }
+
+    /// Speed for the whole displacement once it leaves an elliptical
+    /// deadzone, split between the axes by direction. Rounding the split
+    /// keeps a slight sideways drift from scrolling sideways at one click.
+    /// Each axis gets the speed for the way it scrolls.
+    fn radial(
+        &self,
+        dx: f32,
+        dy: f32,
+        radius_x: f32,
+        radius_y: f32,
+        (horizontal, vertical): (Direction, Direction),
+    ) -> (i32, i32) {
+        let length = dx.hypot(dy);
+        let outside = (dx / radius_x.max(f32::EPSILON)).hypot(dy / radius_y.max(f32::EPSILON));
+        if outside <= 1.0 {
+            return (0, 0);
+        }
+
+        let excess = length * (1.0 - 1.0 / outside);
+        (
+            (self.magnitude(excess, horizontal) * dx / length).round() as i32,
+            (self.magnitude(excess, vertical) * dy / length).round() as i32,
+        )
+    }
+
+    /// Clicks per tick for a distance past the deadzone edge; at least one
+    /// as soon as it's past at all.
+    fn magnitude(&self, excess: f32, direction: Direction) -> f32 {
+        if excess <= 0.0 {
+            return 0.0;
+        }
+
+        let (base_speed, max_speed) = self.limits(direction);
+        let excess = excess * base_speed;
+        let raw = match self.curve {
+            Curve::Linear => excess,
+            Curve::Quadratic => excess * excess,
+            Curve::Logarithmic => excess.ln_1p(),
+            Curve::Constant => f32::INFINITY,
+        };
+        raw.min(max_speed as f32).max(1.0)
+    }
+
+    /// Base and max speed when scrolling `direction`.
+    pub fn limits(&self, direction: Direction) -> (f32, i32) {
+        let speeds = match direction {
+            Direction::Up => self.directions.up,
+            Direction::Down => self.directions.down,
+            Direction::Left => self.directions.left,
+            Direction::Right => self.directions.right,
+        };
+        (
+            speeds.base_speed.unwrap_or(self.base_speed),
+            speeds.max_speed.unwrap_or(self.max_speed),
+        )
+    }
+}
+
+/// How far a distance along one axis goes past the deadzone edge, keeping its sign.
+fn past(distance: f32, deadzone: f32) -> f32 {
+    let excess = (distance.abs() - deadzone).max(0.0);
+    if distance < 0.0 { -excess } else { excess }
+}
+
+/// How long after the trigger is pressed motion still counts as the hand
+/// settling rather than the start of a deliberate move.
+const CALIBRATION_WINDOW: Duration = Duration::from_millis(250);
+/// The deadzone is kept this many times the typical tremor.
+const TREMOR_MARGIN: f32 = 2.5;
+/// How much each press moves the tremor estimate.
+const TREMOR_SMOOTHING: f32 = 0.3;
+const MIN_DEADZONE: f32 = 5.0;
+
+/// Sizes the deadzone to the hand holding the mouse. Right after the
+/// trigger goes down the pointer only wanders by however much the hand
+/// shakes; the largest wander of each press feeds a running estimate, and
+/// the deadzone follows it. Presses that leave the deadzone straight away
+/// are deliberate moves and aren't counted.
+#[derive(Debug)]
+pub struct DeadzoneCalibrator {
+    configured: f32,
+    tremor: f32,
+    pressed_at: Option<SystemTime>,
+    peak: f32,
+}
+
+impl DeadzoneCalibrator {
+    pub fn new(configured: f32) -> Self {
+        DeadzoneCalibrator {
+            configured,
+            tremor: configured / TREMOR_MARGIN,
+            pressed_at: None,
+            peak: 0.0,
+        }
+    }
+
+    pub fn press(&mut self, time: SystemTime) {
+        self.pressed_at = Some(time);
+        self.peak = 0.0;
+    }
+
+    /// Takes the pointer's distance from where the trigger was pressed, and
+    /// returns a new deadzone once this press has been measured.
+    pub fn motion(&mut self, distance: f32, time: SystemTime, deadzone: f32) -> Option<f32> {
+        let pressed_at = self.pressed_at?;
+        if distance > deadzone {
+            self.pressed_at = None;
+            return None;
+        }
+        if time.duration_since(pressed_at).unwrap_or_default() < CALIBRATION_WINDOW {
+            self.peak = self.peak.max(distance);
+            return None;
+        }
+        self.finish()
+    }
+
+    /// Ends the press, returning a new deadzone if it was held long enough
+    /// to measure.
+    pub fn release(&mut self, time: SystemTime) -> Option<f32> {
+        let pressed_at = self.pressed_at?;
+        if time.duration_since(pressed_at).unwrap_or_default() < CALIBRATION_WINDOW {
+            self.pressed_at = None;
+            return None;
+        }
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Option<f32> {
+        self.pressed_at = None;
+        self.tremor += (self.peak - self.tremor) * TREMOR_SMOOTHING;
+        let most = (self.configured * 3.0).max(MIN_DEADZONE);
+        Some((self.tremor * TREMOR_MARGIN).clamp(MIN_DEADZONE, most))
+    }
+}
+
+/// Smoothed pointer velocity, expressed as the distance the pointer would
+/// cover in one `VELOCITY_WINDOW` so it can be fed through the same curve.
+#[derive(Debug, Default)]
+pub struct VelocityTracker {
+    velocity: f32,
+    last_event: Option<SystemTime>,
+}
+
+impl VelocityTracker {
+    pub fn reset(&mut self) {
+        *self = VelocityTracker::default();
+    }
+
+    pub fn update(&mut self, delta: i32, time: SystemTime) -> f32 {
+        let window = VELOCITY_WINDOW.as_secs_f32();
+        let elapsed = self
+            .last_event
+            .and_then(|last| time.duration_since(last).ok())
+            .map_or(window, |d| d.as_secs_f32().clamp(0.001, window));
+        self.last_event = Some(time);
+
+        let instant = delta as f32 / elapsed;
+        self.velocity += (instant - self.velocity) * (elapsed / window);
+        self.velocity * window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DirectionSpeed;
+
+    fn curve() -> SpeedCurve {
+        SpeedCurve {
+            deadzone: 50.0,
+            deadzone_horizontal: 50.0,
+            deadzone_shape: DeadzoneShape::Circle,
+            base_speed: 0.05,
+            max_speed: 5,
+            curve: Curve::Linear,
+            horizontal: false,
+            invert_vertical: false,
+            invert_horizontal: false,
+            directions: DirectionSpeeds::default(),
+            pan_speed: None,
+        }
+    }
+
+    #[test]
+    fn calibrator_with_small_deadzone() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut calibrator = DeadzoneCalibrator::new(1.0);
+        calibrator.press(start);
+        let deadzone = calibrator.release(start + CALIBRATION_WINDOW);
+        assert_eq!(deadzone, Some(MIN_DEADZONE));
+    }
+
+    #[test]
+    fn calibrator_stays_within_bounds() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut calibrator = DeadzoneCalibrator::new(20.0);
+        for _ in 0..20 {
+            calibrator.press(start);
+            calibrator.motion(19.0, start, 20.0);
+            calibrator.release(start + CALIBRATION_WINDOW);
+        }
+        calibrator.press(start);
+        let deadzone = calibrator.release(start + CALIBRATION_WINDOW).unwrap();
+        assert!((MIN_DEADZONE..=60.0).contains(&deadzone));
+    }
+
+    #[test]
+    fn scale_keeps_a_click() {
+        let speed = ScrollSpeed { vertical: -4, horizontal: 1 };
+        assert_eq!(speed.scale(0.5), ScrollSpeed { vertical: -2, horizontal: 1 });
+        assert_eq!(speed.scale(0.0), ScrollSpeed { vertical: -1, horizontal: 1 });
+        assert_eq!(speed.scale(2.0), speed);
+        assert_eq!(ScrollSpeed::default().scale(0.5), ScrollSpeed::default());
+    }
+
+    #[test]
+    fn nudge_never_stops_scrolling() {
+        let speed = ScrollSpeed { vertical: -3, horizontal: 0 };
+        assert_eq!(speed.nudge(2), ScrollSpeed { vertical: -5, horizontal: 0 });
+        assert_eq!(speed.nudge(-10), ScrollSpeed { vertical: -1, horizontal: 0 });
+    }
+
+    #[test]
+    fn limits_per_direction() {
+        let mut curve = curve();
+        curve.directions.up = DirectionSpeed { base_speed: Some(0.1), max_speed: None };
+        curve.directions.left = DirectionSpeed { base_speed: None, max_speed: Some(2) };
+        assert_eq!(curve.limits(Direction::Up), (0.1, 5));
+        assert_eq!(curve.limits(Direction::Down), (0.05, 5));
+        assert_eq!(curve.limits(Direction::Left), (0.05, 2));
+    }
+
+    #[test]
+    fn deadzone_scrolls_nothing() {
+        let curve = curve();
+        assert!(curve.scroll(0.0, 49.0).is_zero());
+        assert_eq!(curve.scroll(0.0, 150.0), ScrollSpeed { vertical: -5, horizontal: 0 });
+        assert_eq!(curve.scroll(0.0, -70.0), ScrollSpeed { vertical: 1, horizontal: 0 });
+    }
+}
@@ -0,0 +1,150 @@
+use crate::speed::ScrollSpeed;
+use std::time::{Duration, SystemTime};
+
+/// Pointer position in device counts.
+pub type Point = (f32, f32);
+
+/// Where one mouse is in a scroll. Every change goes through the methods
+/// below, so a release always ends the scroll however it got there.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ScrollState {
+    /// Trigger up.
+    #[default]
+    Idle,
+    /// Trigger down, pointer still inside the deadzone.
+    Armed { anchor: Point },
+    /// Scrolling at `velocity` clicks per tick.
+    Active { anchor: Point, velocity: ScrollSpeed },
+    /// Just released. Until `until`, presses are taken as the trigger
+    /// bouncing and motion as the hand letting go, and both are ignored.
+    Cooldown { until: SystemTime },
+}
+
+impl ScrollState {
+    /// The trigger went down. Returns the anchor when this starts a scroll.
+    pub fn press(&mut self, position: Point, time: SystemTime) -> Option<Point> {
+        self.settle(time);
+        if *self != ScrollState::Idle {
+            return None;
+        }
+        *self = ScrollState::Armed { anchor: position };
+        Some(position)
+    }
+
+    /// The trigger went up. Returns whether a scroll ended.
+    pub fn release(&mut self, time: SystemTime, cooldown: Duration) -> bool {
+        if self.anchor().is_none() {
+            return false;
+        }
+        *self = ScrollState::Cooldown { until: time + cooldown };
+        true
+    }
+
+    /// Moves the anchor of a scroll that hasn't left the deadzone yet.
+    pub fn reanchor(&mut self, position: Point) {
+        if let ScrollState::Armed { anchor } = self {
+            *anchor = position;
+        }
+    }
+
+    /// Takes the speed the pointer's position asks for. Returns whether it
+    /// changed, i.e. whether the scroll task needs to hear about it.
+    pub fn set_speed(&mut self, speed: ScrollSpeed) -> bool {
+        let Some(anchor) = self.anchor() else {
+            return false;
+        };
+        let next = if speed.is_zero() {
+            ScrollState::Armed { anchor }
+        } else {
+            ScrollState::Active { anchor, velocity: speed }
+        };
+        let changed = next != *self;
+        *self = next;
+        changed
+    }
+
+    /// Lets a finished cooldown lapse.
+    pub fn settle(&mut self, time: SystemTime) {
+        if let ScrollState::Cooldown { until, .. } = *self
+            && time >= until
+        {
+            *self = ScrollState::Idle;
+        }
+    }
+
+    pub fn in_cooldown(&self, time: SystemTime) -> bool {
+        matches!(*self, ScrollState::Cooldown { until } if time < until)
+    }
+
+    /// Where the scroll started, while the trigger is held.
+    pub fn anchor(&self) -> Option<Point> {
+        match *self {
+            ScrollState::Armed { anchor } | ScrollState::Active { anchor, .. } => Some(anchor),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: SystemTime = SystemTime::UNIX_EPOCH;
+    const COOLDOWN: Duration = Duration::from_millis(30);
+
+    #[test]
+    fn release_inside_deadzone() {
+        let mut state = ScrollState::default();
+        assert_eq!(state.press((10.0, 20.0), START), Some((10.0, 20.0)));
+        assert!(!state.set_speed(ScrollSpeed::default()));
+        assert_eq!(state, ScrollState::Armed { anchor: (10.0, 20.0) });
+
+        assert!(state.release(START, Duration::ZERO));
+        state.settle(START);
+        assert_eq!(state, ScrollState::Idle);
+    }
+
+    #[test]
+    fn speed_starts_scrolling() {
+        let mut state = ScrollState::default();
+        state.press((0.0, 0.0), START);
+        let speed = ScrollSpeed { vertical: 2, horizontal: 0 };
+        assert!(state.set_speed(speed));
+        assert_eq!(state, ScrollState::Active { anchor: (0.0, 0.0), velocity: speed });
+        assert!(!state.set_speed(speed));
+        assert!(state.set_speed(ScrollSpeed::default()));
+        assert_eq!(state, ScrollState::Armed { anchor: (0.0, 0.0) });
+    }
+
+    #[test]
+    fn cooldown_after_release() {
+        let mut state = ScrollState::default();
+        state.press((0.0, 0.0), START);
+        state.set_speed(ScrollSpeed { vertical: 0, horizontal: -1 });
+        assert!(state.release(START, COOLDOWN));
+        assert_eq!(state, ScrollState::Cooldown { until: START + COOLDOWN });
+
+        // A bounce during the cooldown doesn't start another scroll
+        assert_eq!(state.press((0.0, 0.0), START + COOLDOWN / 2), None);
+        assert!(state.in_cooldown(START + COOLDOWN / 2));
+        assert!(!state.set_speed(ScrollSpeed { vertical: 1, horizontal: 0 }));
+
+        state.settle(START + COOLDOWN);
+        assert_eq!(state, ScrollState::Idle);
+        assert!(!state.release(START + COOLDOWN, COOLDOWN));
+    }
+
+    #[test]
+    fn reanchor_moves_the_anchor() {
+        let mut state = ScrollState::default();
+        state.press((0.0, 0.0), START);
+        state.reanchor((5.0, -5.0));
+        assert_eq!(state, ScrollState::Armed { anchor: (5.0, -5.0) });
+
+        // Once scrolling, the anchor stays where the scroll started
+        let speed = ScrollSpeed { vertical: 1, horizontal: 0 };
+        state.set_speed(speed);
+        state.reanchor((9.0, 9.0));
+        assert_eq!(state, ScrollState::Active { anchor: (5.0, -5.0), velocity: speed });
+    }
+}
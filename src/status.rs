@@ -0,0 +1,35 @@
+use std::fmt;
+use std::sync::OnceLock;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// Lines kept for a watcher that falls behind; older ones are dropped.
+const BACKLOG: usize = 256;
+
+static WATCHERS: OnceLock<Sender<String>> = OnceLock::new();
+
+/// Follows everything reported from now on, for `autoscroll watch`.
+pub fn subscribe() -> Receiver<String> {
+    WATCHERS.get_or_init(|| broadcast::channel(BACKLOG).0).subscribe()
+}
+
+/// Whether anyone is watching, so nothing is formatted when nobody is.
+pub fn watched() -> bool {
+    WATCHERS.get().is_some_and(|tx| tx.receiver_count() > 0)
+}
+
+/// Used by the `status!` macro.
+pub fn write(args: fmt::Arguments) {
+    if let Some(tx) = WATCHERS.get() {
+        let _ = tx.send(args.to_string());
+    }
+}
+
+/// Tells anyone watching why something did or didn't happen, mostly why a
+/// trigger press didn't scroll.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::status::watched() {
+            $crate::status::write(format_args!($($arg)*));
+        }
+    };
+}
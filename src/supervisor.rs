@@ -0,0 +1,52 @@
+use crate::scroll;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The wait before the first restart, doubled for each crash that follows
+/// soon after, up to `MAX_BACKOFF`.
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Running this long without a crash starts the backoff over.
+const STABLE: Duration = Duration::from_secs(300);
+
+/// Brings readers and scroll tasks back after one panics or its output
+/// fails. The task's devices, grabs included, are released as it unwinds;
+/// restarting them waits a little, and longer each time, so a crash that
+/// happens on every start can't spin.
+pub struct Supervisor {
+    backoff: Duration,
+    last_crash: Option<Instant>,
+    restart_at: Option<Instant>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor { backoff: FIRST_BACKOFF, last_crash: None, restart_at: None }
+    }
+
+    /// Notes that `what` went down, and schedules a restart.
+    pub fn crashed(&mut self, what: &str) {
+        let now = Instant::now();
+        self.backoff = match self.last_crash {
+            Some(last) if now - last < STABLE => (self.backoff * 2).min(MAX_BACKOFF),
+            _ => FIRST_BACKOFF,
+        };
+        self.last_crash = Some(now);
+        if self.restart_at.is_none() {
+            self.restart_at = Some(now + self.backoff);
+        }
+        error!("{}, restarting in {}s", what, self.backoff.as_secs());
+        status!("{}, restarting in {}s", what, self.backoff.as_secs());
+    }
+
+    /// Whether a restart is still to come.
+    pub fn pending(&self) -> bool {
+        self.restart_at.is_some()
+    }
+
+    /// Waits until it's time to restart what crashed.
+    pub async fn due(&mut self) {
+        scroll::sleep_until(self.restart_at).await;
+        self.restart_at = None;
+    }
+}
@@ -0,0 +1,122 @@
+use crate::config::SwitchConfig;
+use crate::speed::ScrollSpeed;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, PartialEq)]
+pub enum SwitchAction {
+    Start(ScrollSpeed),
+    Reverse(ScrollSpeed),
+    Stop,
+}
+
+/// Scrolling for people who can press a button but can't hold it down or
+/// steer the pointer. A short press starts scrolling down at a fixed
+/// speed, the next one reverses it, and a long press stops.
+#[derive(Debug)]
+pub struct Switch {
+    speed: i32,
+    long_press: Duration,
+    pressed_at: Option<SystemTime>,
+    /// Clicks per tick while scrolling, using the wheel's sign.
+    current: Option<i32>,
+}
+
+impl Switch {
+    pub fn new(settings: &SwitchConfig) -> Self {
+        Switch {
+            speed: settings.speed.max(1),
+            long_press: Duration::from_millis(settings.long_press_ms),
+            pressed_at: None,
+            current: None,
+        }
+    }
+
+    pub fn press(&mut self, time: SystemTime) {
+        self.pressed_at = Some(time);
+    }
+
+    /// Drops whatever the switch was doing. Returns whether it was scrolling.
+    pub fn cancel(&mut self) -> bool {
+        self.pressed_at = None;
+        self.current.take().is_some()
+    }
+
+    /// Acts on a press once the button comes back up, when its length is known.
+    pub fn release(&mut self, time: SystemTime) -> Option<SwitchAction> {
+        let held = time.duration_since(self.pressed_at.take()?).unwrap_or_default();
+        if held >= self.long_press {
+            return self.current.take().map(|_| SwitchAction::Stop);
+        }
+
+        let vertical = match self.current {
+            Some(current) => -current,
+            None => -self.speed,
+        };
+        let speed = ScrollSpeed { vertical, horizontal: 0 };
+        Some(match self.current.replace(vertical) {
+            Some(_) => SwitchAction::Reverse(speed),
+            None => SwitchAction::Start(speed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: SystemTime = SystemTime::UNIX_EPOCH;
+    const SHORT: Duration = Duration::from_millis(100);
+    const LONG: Duration = Duration::from_millis(600);
+
+    fn switch() -> Switch {
+        Switch::new(&SwitchConfig { enabled: true, speed: 2, long_press_ms: 600 })
+    }
+
+    /// Presses at `at` for `held`, returning what the release does.
+    fn click(switch: &mut Switch, at: Duration, held: Duration) -> Option<SwitchAction> {
+        switch.press(START + at);
+        switch.release(START + at + held)
+    }
+
+    fn down(vertical: i32) -> ScrollSpeed {
+        ScrollSpeed { vertical, horizontal: 0 }
+    }
+
+    #[test]
+    fn presses_start_and_reverse() {
+        let mut switch = switch();
+        assert_eq!(click(&mut switch, Duration::ZERO, SHORT), Some(SwitchAction::Start(down(-2))));
+        assert_eq!(click(&mut switch, LONG, SHORT), Some(SwitchAction::Reverse(down(2))));
+        assert_eq!(click(&mut switch, LONG * 2, SHORT), Some(SwitchAction::Reverse(down(-2))));
+    }
+
+    #[test]
+    fn long_press_stops() {
+        let mut switch = switch();
+        click(&mut switch, Duration::ZERO, SHORT);
+        assert_eq!(click(&mut switch, LONG, LONG), Some(SwitchAction::Stop));
+        // Nothing left to stop
+        assert_eq!(click(&mut switch, LONG * 3, LONG), None);
+        assert_eq!(click(&mut switch, LONG * 5, SHORT), Some(SwitchAction::Start(down(-2))));
+    }
+
+    #[test]
+    fn release_without_a_press_is_ignored() {
+        let mut switch = switch();
+        click(&mut switch, Duration::ZERO, SHORT);
+        // The scroll keeps going however many stray releases come in
+        assert_eq!(switch.release(START + LONG), None);
+        assert_eq!(switch.release(START + LONG * 2), None);
+        assert_eq!(click(&mut switch, LONG * 3, SHORT), Some(SwitchAction::Reverse(down(2))));
+    }
+
+    #[test]
+    fn cancel_ends_the_scroll() {
+        let mut switch = switch();
+        assert!(!switch.cancel());
+        click(&mut switch, Duration::ZERO, SHORT);
+        switch.press(START + LONG);
+        assert!(switch.cancel());
+        assert_eq!(switch.release(START + LONG + SHORT), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::absolute::{self, COUNTS_PER_MM};
+use crate::config::TouchpadConfig;
+use crate::diagnose;
+use crate::speed::SpeedCurve;
+use crate::scroll::{PointerSender, ScrollCommand};
+use evdev::{AbsoluteAxisType, Device, InputEventKind, Key, PropType, Synchronization};
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+/// Used when the kernel doesn't report a resolution for an axis.
+const ASSUMED_HEIGHT_MM: f32 = 60.0;
+/// How far the fingers may drift during the hold before it restarts.
+const HOLD_SLOP_MM: f32 = 2.0;
+
+pub fn is_touchpad(device: &Device) -> bool {
+    let has_slots = device.supported_absolute_axes().is_some_and(|axes| {
+        axes.contains(AbsoluteAxisType::ABS_MT_SLOT)
+            && axes.contains(AbsoluteAxisType::ABS_MT_POSITION_Y)
+    });
+    let has_fingers = device
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(Key::BTN_TOOL_FINGER));
+
+    has_slots && has_fingers && device.properties().contains(PropType::POINTER)
+}
+
+/// Finger positions in millimetres.
+type Point = (f32, f32);
+
+enum Gesture {
+    Idle,
+    Holding { since: SystemTime, start: Point },
+    Scrolling { origin: Point },
+}
+
+/// Reads multitouch slots from a touchpad and starts scrolling once the
+/// configured number of fingers has been resting for the hold time. The
+/// average finger position then acts like the pointer does for a mouse,
+/// until any finger lifts or lands. The touchpad isn't grabbed, so the
+/// desktop still sees the fingers too.
+pub async fn run(
+    device: Device,
+    settings: TouchpadConfig,
+    curve: SpeedCurve,
+    mut tx: PointerSender,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let x_units_per_mm =
+        absolute::units_per_mm(&device, AbsoluteAxisType::ABS_MT_POSITION_X, ASSUMED_HEIGHT_MM)?;
+    let y_units_per_mm =
+        absolute::units_per_mm(&device, AbsoluteAxisType::ABS_MT_POSITION_Y, ASSUMED_HEIGHT_MM)?;
+    let slot_info = device.get_abs_state()?[AbsoluteAxisType::ABS_MT_SLOT.0 as usize];
+    let hold = Duration::from_millis(settings.hold_ms);
+
+    let mut slots: Vec<Option<(i32, i32)>> = vec![None; slot_info.maximum.max(0) as usize + 1];
+    let mut current_slot = slot_info.value.max(0) as usize;
+    let mut gesture = Gesture::Idle;
+
+    let name = device.name().unwrap_or("Unknown").to_string();
+    let mut events = device.into_event_stream()?;
+    loop {
+        let ev = events.next_event().await?;
+        trace!("{}: {:?} {}", name, ev.kind(), ev.value());
+        diagnose::input_event(ev.timestamp());
+        match ev.kind() {
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_SLOT) => {
+                current_slot = ev.value().max(0) as usize;
+            }
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+                if let Some(slot) = slots.get_mut(current_slot) {
+                    *slot = if ev.value() < 0 { None } else { Some(slot.unwrap_or((0, 0))) };
+                }
+            }
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                if let Some(Some((x, _))) = slots.get_mut(current_slot) {
+                    *x = ev.value();
+                }
+            }
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                if let Some(Some((_, y))) = slots.get_mut(current_slot) {
+                    *y = ev.value();
+                }
+            }
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                let touching: Vec<(i32, i32)> = slots.iter().flatten().copied().collect();
+                let fingers = touching.len();
+                let position = if fingers > 0 {
+                    let sum_x: i32 = touching.iter().map(|(x, _)| x).sum();
+                    let sum_y: i32 = touching.iter().map(|(_, y)| y).sum();
+                    (
+                        sum_x as f32 / fingers as f32 / x_units_per_mm,
+                        sum_y as f32 / fingers as f32 / y_units_per_mm,
+                    )
+                } else {
+                    (0.0, 0.0)
+                };
+
+                gesture = match gesture {
+                    Gesture::Idle | Gesture::Holding { .. } if fingers != settings.fingers => {
+                        Gesture::Idle
+                    }
+                    Gesture::Idle => Gesture::Holding { since: ev.timestamp(), start: position },
+                    Gesture::Holding { start, .. } if distance(start, position) > HOLD_SLOP_MM => {
+                        Gesture::Holding { since: ev.timestamp(), start: position }
+                    }
+                    Gesture::Holding { since, start } => {
+                        let held = ev.timestamp().duration_since(since).unwrap_or_default();
+                        if held >= hold {
+                            trace!("Start touchpad scroll");
+                            tx.send(ScrollCommand::Start { device: name.clone() })?;
+                            Gesture::Scrolling { origin: position }
+                        } else {
+                            Gesture::Holding { since, start }
+                        }
+                    }
+                    // A finger landing would move the average, and with it
+                    // the distance from the origin, in one jump
+                    Gesture::Scrolling { .. } if fingers != settings.fingers => {
+                        trace!("Stop touchpad scroll");
+                        tx.send(ScrollCommand::Stop)?;
+                        Gesture::Idle
+                    }
+                    Gesture::Scrolling { origin } => {
+                        let dx = (position.0 - origin.0) * COUNTS_PER_MM;
+                        let dy = (position.1 - origin.1) * COUNTS_PER_MM;
+                        tx.send(ScrollCommand::Update(curve.scroll(dx, dy)))?;
+                        Gesture::Scrolling { origin }
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
@@ -0,0 +1,36 @@
+use futures_util::stream::StreamExt;
+use std::error::Error;
+use zbus::proxy::PropertyStream;
+use zbus::{proxy, Connection};
+
+#[proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    #[zbus(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+/// Follows UPower's idea of whether the machine runs on battery.
+pub struct PowerWatch {
+    changes: PropertyStream<'static, bool>,
+}
+
+impl PowerWatch {
+    /// Connects to UPower, also returning whether it's on battery now.
+    pub async fn connect() -> Result<(PowerWatch, bool), Box<dyn Error>> {
+        let conn = Connection::system().await?;
+        let upower = UPowerProxy::new(&conn).await?;
+        let on_battery = upower.on_battery().await?;
+        let changes = upower.receive_on_battery_changed().await;
+        Ok((PowerWatch { changes }, on_battery))
+    }
+
+    /// Waits for the machine to be plugged in or unplugged.
+    pub async fn changed(&mut self) -> Result<bool, Box<dyn Error>> {
+        let change = self.changes.next().await.ok_or("UPower went away")?;
+        Ok(change.get().await?)
+    }
+}
@@ -0,0 +1,221 @@
+use crate::config::WheelConfig;
+use evdev::{EventType, InputEvent, InputEventKind, RelativeAxisType};
+use std::time::{Duration, SystemTime};
+
+/// Hi-res wheel units per notch, as defined by the kernel.
+pub const HI_RES_PER_NOTCH: i32 = 120;
+/// Notches further apart than this count as separate flicks.
+const ACCELERATION_RESET: Duration = Duration::from_millis(200);
+
+/// Turns fractional hi-res wheel motion into whole hi-res units and legacy
+/// notches. Remainders carry over, so nothing is lost to rounding and the
+/// notches stay in step with the hi-res events.
+#[derive(Debug, Default)]
+pub struct HiResAccumulator {
+    hi_res_remainder: f32,
+    notch_remainder: i32,
+}
+
+impl HiResAccumulator {
+    /// Returns the hi-res units and whole notches to emit for `hi_res` more motion.
+    pub fn add(&mut self, hi_res: f32) -> (i32, i32) {
+        let scaled = hi_res + self.hi_res_remainder;
+        let hi_res_out = scaled.trunc() as i32;
+        self.hi_res_remainder = scaled.fract();
+
+        self.notch_remainder += hi_res_out;
+        let notches = self.notch_remainder / HI_RES_PER_NOTCH;
+        self.notch_remainder %= HI_RES_PER_NOTCH;
+
+        (hi_res_out, notches)
+    }
+
+    pub fn reset(&mut self) {
+        *self = HiResAccumulator::default();
+    }
+}
+
+/// Rescales one physical wheel axis by a constant factor, speeding it up
+/// further the faster the wheel spins.
+#[derive(Debug)]
+pub struct WheelAxis {
+    settings: WheelConfig,
+    last_event: Option<(SystemTime, i32)>,
+    accumulator: HiResAccumulator,
+}
+
+impl WheelAxis {
+    pub fn new(settings: &WheelConfig) -> Self {
+        WheelAxis {
+            settings: settings.clone(),
+            last_event: None,
+            accumulator: HiResAccumulator::default(),
+        }
+    }
+
+    /// Takes the wheel motion from one frame in hi-res units and returns the
+    /// hi-res units and whole notches to emit instead.
+    pub fn scale(&mut self, hi_res: i32, time: SystemTime) -> (i32, i32) {
+        let direction = hi_res.signum();
+        let rate = match self.last_event {
+            Some((last, last_direction)) if last_direction == direction => time
+                .duration_since(last)
+                .ok()
+                .filter(|elapsed| *elapsed < ACCELERATION_RESET)
+                .map_or(0.0, |elapsed| {
+                    let notches = hi_res.abs() as f32 / HI_RES_PER_NOTCH as f32;
+                    notches / elapsed.as_secs_f32().max(0.001)
+                }),
+            _ => {
+                // Reversing direction shouldn't fling leftover motion the other way
+                self.accumulator.reset();
+                0.0
+            }
+        };
+        self.last_event = Some((time, direction));
+
+        let acceleration = (1.0 + self.settings.acceleration * rate).min(self.settings.max_factor.max(1.0));
+        let factor = self.settings.factor * acceleration;
+        self.accumulator.add(hi_res as f32 * factor)
+    }
+}
+
+/// Collects a frame's wheel events from a grabbed mouse and replaces them
+/// with rescaled ones once the frame is complete.
+#[derive(Debug)]
+pub struct WheelRescaler {
+    vertical: WheelAxis,
+    horizontal: WheelAxis,
+    /// Wheel motion in the current frame, as (hi-res units, notches)
+    frame_vertical: (Option<i32>, i32),
+    frame_horizontal: (Option<i32>, i32),
+}
+
+impl WheelRescaler {
+    pub fn new(settings: &WheelConfig) -> Self {
+        WheelRescaler {
+            vertical: WheelAxis::new(settings),
+            horizontal: WheelAxis::new(settings),
+            frame_vertical: (None, 0),
+            frame_horizontal: (None, 0),
+        }
+    }
+
+    /// Keeps wheel events back for rescaling; anything else is left alone.
+    pub fn take(&mut self, event: &InputEvent) -> bool {
+        match event.kind() {
+            InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL_HI_RES) => {
+                self.frame_vertical.0 = Some(event.value())
+            }
+            InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) => {
+                self.frame_vertical.1 = event.value()
+            }
+            InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL_HI_RES) => {
+                self.frame_horizontal.0 = Some(event.value())
+            }
+            InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL) => {
+                self.frame_horizontal.1 = event.value()
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// The rescaled wheel events for the frame that just ended. Mice without
+    /// hi-res scrolling only report notches, which count as 120 units each.
+    pub fn finish_frame(&mut self, time: SystemTime) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        for (frame, axis, hi_res_axis, notch_axis) in [
+            (
+                &mut self.frame_vertical,
+                &mut self.vertical,
+                RelativeAxisType::REL_WHEEL_HI_RES,
+                RelativeAxisType::REL_WHEEL,
+            ),
+            (
+                &mut self.frame_horizontal,
+                &mut self.horizontal,
+                RelativeAxisType::REL_HWHEEL_HI_RES,
+                RelativeAxisType::REL_HWHEEL,
+            ),
+        ] {
+            let hi_res = frame.0.unwrap_or(frame.1 * HI_RES_PER_NOTCH);
+            *frame = (None, 0);
+            if hi_res == 0 {
+                continue;
+            }
+
+            let (hi_res, notches) = axis.scale(hi_res, time);
+            if hi_res != 0 {
+                events.push(InputEvent::new(EventType::RELATIVE, hi_res_axis.0, hi_res));
+            }
+            if notches != 0 {
+                events.push(InputEvent::new(EventType::RELATIVE, notch_axis.0, notches));
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rescaler(factor: f32) -> WheelRescaler {
+        WheelRescaler::new(&WheelConfig { factor, ..WheelConfig::default() })
+    }
+
+    /// Feeds one frame of vertical wheel events at `second`, far enough apart
+    /// not to accelerate, returning the (hi-res units, notches) sent for it.
+    fn frame(rescaler: &mut WheelRescaler, second: u64, events: &[(RelativeAxisType, i32)]) -> (i32, i32) {
+        for (axis, value) in events {
+            assert!(rescaler.take(&InputEvent::new(EventType::RELATIVE, axis.0, *value)));
+        }
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(second);
+        let (mut hi_res, mut notches) = (0, 0);
+        for event in rescaler.finish_frame(time) {
+            match event.kind() {
+                InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL_HI_RES) => hi_res += event.value(),
+                InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) => notches += event.value(),
+                kind => panic!("unexpected event: {:?}", kind),
+            }
+        }
+        (hi_res, notches)
+    }
+
+    #[test]
+    fn half_speed_over_two_notches() {
+        let mut rescaler = rescaler(0.5);
+        let notch = [(RelativeAxisType::REL_WHEEL, 1)];
+        assert_eq!(frame(&mut rescaler, 1, &notch), (60, 0));
+        assert_eq!(frame(&mut rescaler, 2, &notch), (60, 1));
+    }
+
+    #[test]
+    fn triple_speed_keeps_hi_res_in_step() {
+        let mut rescaler = rescaler(3.0);
+        let notch = [(RelativeAxisType::REL_WHEEL_HI_RES, 120), (RelativeAxisType::REL_WHEEL, 1)];
+        for second in 1..=3 {
+            assert_eq!(frame(&mut rescaler, second, &notch), (360, 3));
+        }
+    }
+
+    #[test]
+    fn scrolling_down() {
+        let mut rescaler = rescaler(0.5);
+        let notch = [(RelativeAxisType::REL_WHEEL_HI_RES, -120), (RelativeAxisType::REL_WHEEL, -1)];
+        assert_eq!(frame(&mut rescaler, 1, &notch), (-60, 0));
+        assert_eq!(frame(&mut rescaler, 2, &notch), (-60, -1));
+    }
+
+    #[test]
+    fn fractions_carry_over() {
+        let mut accumulator = HiResAccumulator::default();
+        let mut total = (0, 0);
+        for _ in 0..16 {
+            let (hi_res, notches) = accumulator.add(7.5);
+            total = (total.0 + hi_res, total.1 + notches);
+        }
+        assert_eq!(total, (120, 1));
+    }
+}
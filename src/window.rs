@@ -0,0 +1,81 @@
+use std::error::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+
+/// The focused window's WM_CLASS, e.g. instance "slack" and class "Slack".
+#[derive(Debug, Clone, Default)]
+pub struct WindowClass {
+    pub instance: String,
+    pub class: String,
+}
+
+struct X11 {
+    conn: RustConnection,
+    root: Window,
+    net_active_window: Atom,
+}
+
+impl X11 {
+    fn connect() -> Result<X11, Box<dyn Error>> {
+        let (conn, screen) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let net_active_window = conn.intern_atom(false, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+        Ok(X11 { conn, root, net_active_window })
+    }
+
+    fn active_class(&self) -> Result<Option<WindowClass>, Box<dyn Error>> {
+        let active = self
+            .conn
+            .get_property(false, self.root, self.net_active_window, AtomEnum::WINDOW, 0, 1)?
+            .reply()?;
+        let window = match active.value32().and_then(|mut v| v.next()) {
+            Some(window) if window != 0 => window,
+            _ => return Ok(None),
+        };
+
+        let wm_class = self
+            .conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?
+            .reply()?;
+        let mut parts = wm_class
+            .value
+            .split(|b| *b == 0)
+            .map(|part| String::from_utf8_lossy(part).into_owned());
+
+        Ok(Some(WindowClass {
+            instance: parts.next().unwrap_or_default(),
+            class: parts.next().unwrap_or_default(),
+        }))
+    }
+}
+
+/// Asks the X server which window has focus. Connects lazily and reconnects
+/// after errors, so it copes with the daemon starting before the session.
+#[derive(Default)]
+pub struct WindowTracker {
+    x11: Option<X11>,
+}
+
+impl WindowTracker {
+    pub fn active_class(&mut self) -> Option<WindowClass> {
+        if self.x11.is_none() {
+            match X11::connect() {
+                Ok(x11) => self.x11 = Some(x11),
+                Err(e) => {
+                    debug!("Can't look up the active window: {}", e);
+                    return None;
+                }
+            }
+        }
+
+        match self.x11.as_ref()?.active_class() {
+            Ok(class) => class,
+            Err(e) => {
+                debug!("Lost the X11 connection: {}", e);
+                self.x11 = None;
+                None
+            }
+        }
+    }
+}
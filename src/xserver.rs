@@ -0,0 +1,21 @@
+use std::error::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::ConnectionExt;
+
+/// Whether an X server answers.
+pub fn reachable() -> bool {
+    x11rb::connect(None).is_ok()
+}
+
+/// Whether the X server's pointer mapping swaps buttons 1 and 3.
+pub fn left_handed() -> Result<bool, Box<dyn Error>> {
+    let (conn, _) = x11rb::connect(None)?;
+    let map = conn.get_pointer_mapping()?.reply()?.map;
+    Ok(map.len() >= 3 && map[0] == 3 && map[2] == 1)
+}
+
+/// The root window's height in pixels.
+pub fn screen_height() -> Result<u32, Box<dyn Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    Ok(conn.setup().roots[screen].height_in_pixels as u32)
+}